@@ -0,0 +1,5 @@
+// The migrations are embedded in the binary, it's rebuilt when they change
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=migrations_postgres");
+}
@@ -0,0 +1,7 @@
+CREATE TABLE
+    email_login_token (
+        token_hash TEXT PRIMARY KEY NOT NULL,
+        email TEXT NOT NULL,
+        created_at DATETIME NOT NULL,
+        expires_at DATETIME NOT NULL
+    );
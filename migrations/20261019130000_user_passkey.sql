@@ -0,0 +1,19 @@
+CREATE TABLE
+    user_passkey (
+        id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        passkey TEXT NOT NULL,
+        created_at DATETIME NOT NULL,
+        last_used_at DATETIME,
+        FOREIGN KEY (user_id) REFERENCES user(id)
+    );
+
+CREATE TABLE
+    passkey_challenge (
+        id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT,
+        state TEXT NOT NULL,
+        expires_at DATETIME NOT NULL,
+        FOREIGN KEY (user_id) REFERENCES user(id)
+    );
@@ -0,0 +1,12 @@
+CREATE TABLE
+    user_totp (
+        user_id TEXT PRIMARY KEY NOT NULL,
+        secret TEXT NOT NULL,
+        created_at DATETIME NOT NULL,
+        confirmed_at DATETIME,
+        last_used_step INTEGER,
+        FOREIGN KEY (user_id) REFERENCES user(id)
+    );
+
+ALTER TABLE user_session ADD COLUMN second_factor_pending BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE user_session ADD COLUMN second_factor_attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+CREATE TABLE
+    user_recovery_code (
+        id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        code_hash TEXT NOT NULL UNIQUE,
+        created_at DATETIME NOT NULL,
+        used_at DATETIME,
+        FOREIGN KEY (user_id) REFERENCES user(id)
+    );
@@ -0,0 +1,10 @@
+CREATE TABLE
+    user_credential (
+        user_id TEXT PRIMARY KEY NOT NULL,
+        username TEXT NOT NULL UNIQUE,
+        password_hash TEXT NOT NULL,
+        failed_attempts INTEGER NOT NULL DEFAULT 0,
+        locked_until DATETIME,
+        updated_at DATETIME NOT NULL,
+        FOREIGN KEY (user_id) REFERENCES user(id)
+    );
@@ -0,0 +1,21 @@
+CREATE TABLE
+    role (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        -- Space separated list of permissions
+        permissions TEXT NOT NULL DEFAULT ''
+    );
+
+CREATE TABLE
+    user_role (
+        user_id TEXT NOT NULL,
+        role_id INTEGER NOT NULL,
+        PRIMARY KEY (user_id, role_id),
+        FOREIGN KEY (user_id) REFERENCES user(id),
+        FOREIGN KEY (role_id) REFERENCES role(id)
+    );
+
+INSERT INTO
+    role (name, permissions)
+VALUES
+    ('admin', 'users:read users:write sessions:write roles:write');
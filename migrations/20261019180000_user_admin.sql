@@ -0,0 +1,10 @@
+ALTER TABLE user ADD COLUMN created_at DATETIME;
+ALTER TABLE user ADD COLUMN last_login_at DATETIME;
+ALTER TABLE user ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The existing users were created at least when they first signed in
+UPDATE user
+SET created_at = COALESCE(
+        (SELECT MIN(created_at) FROM user_session WHERE user_session.user_id = user.id),
+        CURRENT_TIMESTAMP
+    );
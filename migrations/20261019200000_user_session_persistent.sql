@@ -0,0 +1,2 @@
+-- Sessions that were kept signed in with "remember me", the existing sessions had persistent cookies
+ALTER TABLE user_session ADD COLUMN persistent BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,4 @@
+-- Sessions are identified by the SHA-256 hash of a random token, the token is only in the cookie.
+-- The existing sessions used their id as the cookie value, so they are invalidated.
+DELETE FROM user_session;
+ALTER TABLE user_session RENAME COLUMN id TO token_hash;
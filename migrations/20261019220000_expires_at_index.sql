@@ -0,0 +1,4 @@
+-- The expired rows are deleted in batches by the background sweeper
+CREATE INDEX user_session_expires_at ON user_session (expires_at);
+CREATE INDEX email_login_token_expires_at ON email_login_token (expires_at);
+CREATE INDEX passkey_challenge_expires_at ON passkey_challenge (expires_at);
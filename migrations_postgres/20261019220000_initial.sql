@@ -0,0 +1,108 @@
+-- The schema of the sqlite migrations up to this version, the next migrations are added to both directories
+CREATE TABLE
+    "user" (
+        id UUID PRIMARY KEY NOT NULL,
+        account_id TEXT NOT NULL,
+        provider TEXT NOT NULL,
+        username TEXT NOT NULL,
+        image_url TEXT,
+        created_at TIMESTAMP,
+        last_login_at TIMESTAMP,
+        disabled BOOLEAN NOT NULL DEFAULT FALSE,
+        UNIQUE (account_id, provider)
+    );
+
+CREATE TABLE
+    user_session (
+        token_hash TEXT PRIMARY KEY NOT NULL,
+        -- Identifies the session in the session list, like the rowid on sqlite
+        handle BIGINT GENERATED ALWAYS AS IDENTITY UNIQUE,
+        user_id UUID NOT NULL REFERENCES "user" (id),
+        created_at TIMESTAMP NOT NULL,
+        expires_at TIMESTAMP NOT NULL,
+        second_factor_pending BOOLEAN NOT NULL DEFAULT FALSE,
+        second_factor_attempts BIGINT NOT NULL DEFAULT 0,
+        ip_address TEXT,
+        user_agent TEXT,
+        last_seen_at TIMESTAMP,
+        persistent BOOLEAN NOT NULL DEFAULT TRUE
+    );
+
+CREATE TABLE
+    email_login_token (
+        token_hash TEXT PRIMARY KEY NOT NULL,
+        email TEXT NOT NULL,
+        created_at TIMESTAMP NOT NULL,
+        expires_at TIMESTAMP NOT NULL
+    );
+
+CREATE TABLE
+    user_passkey (
+        id TEXT PRIMARY KEY NOT NULL,
+        user_id UUID NOT NULL REFERENCES "user" (id),
+        name TEXT NOT NULL,
+        passkey TEXT NOT NULL,
+        created_at TIMESTAMP NOT NULL,
+        last_used_at TIMESTAMP
+    );
+
+CREATE TABLE
+    passkey_challenge (
+        id UUID PRIMARY KEY NOT NULL,
+        user_id UUID REFERENCES "user" (id),
+        state TEXT NOT NULL,
+        expires_at TIMESTAMP NOT NULL
+    );
+
+CREATE TABLE
+    user_totp (
+        user_id UUID PRIMARY KEY NOT NULL REFERENCES "user" (id),
+        secret TEXT NOT NULL,
+        created_at TIMESTAMP NOT NULL,
+        confirmed_at TIMESTAMP,
+        last_used_step BIGINT
+    );
+
+CREATE TABLE
+    user_recovery_code (
+        id UUID PRIMARY KEY NOT NULL,
+        user_id UUID NOT NULL REFERENCES "user" (id),
+        code_hash TEXT NOT NULL UNIQUE,
+        created_at TIMESTAMP NOT NULL,
+        used_at TIMESTAMP
+    );
+
+CREATE TABLE
+    user_credential (
+        user_id UUID PRIMARY KEY NOT NULL REFERENCES "user" (id),
+        username TEXT NOT NULL UNIQUE,
+        password_hash TEXT NOT NULL,
+        failed_attempts BIGINT NOT NULL DEFAULT 0,
+        locked_until TIMESTAMP,
+        updated_at TIMESTAMP NOT NULL
+    );
+
+CREATE TABLE
+    role (
+        id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        -- Space separated list of permissions
+        permissions TEXT NOT NULL DEFAULT ''
+    );
+
+CREATE TABLE
+    user_role (
+        user_id UUID NOT NULL REFERENCES "user" (id),
+        role_id BIGINT NOT NULL REFERENCES role (id),
+        PRIMARY KEY (user_id, role_id)
+    );
+
+INSERT INTO
+    role (name, permissions)
+VALUES
+    ('admin', 'users:read users:write sessions:write roles:write');
+
+-- The expired rows are deleted in batches by the background sweeper
+CREATE INDEX user_session_expires_at ON user_session (expires_at);
+CREATE INDEX email_login_token_expires_at ON email_login_token (expires_at);
+CREATE INDEX passkey_challenge_expires_at ON passkey_challenge (expires_at);
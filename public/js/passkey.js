@@ -0,0 +1,126 @@
+// Helpers to exchange WebAuthn ceremonies with the server, binary fields are sent as base64url strings.
+
+function base64UrlToBuffer(value) {
+  const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+  const padded = base64.padEnd(base64.length + ((4 - (base64.length % 4)) % 4), "=");
+  const binary = atob(padded);
+  return Uint8Array.from(binary, (c) => c.charCodeAt(0)).buffer;
+}
+
+function bufferToBase64Url(buffer) {
+  const binary = String.fromCharCode(...new Uint8Array(buffer));
+  return btoa(binary).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}
+
+async function postJson(url, body) {
+  const response = await fetch(url, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+
+  if (!response.ok) {
+    throw new Error(`Request to ${url} failed with status ${response.status}`);
+  }
+
+  return response;
+}
+
+async function registerPasskey(name) {
+  const response = await postJson("/api/auth/passkey/register/start");
+  const { publicKey } = await response.json();
+
+  publicKey.challenge = base64UrlToBuffer(publicKey.challenge);
+  publicKey.user.id = base64UrlToBuffer(publicKey.user.id);
+  publicKey.excludeCredentials = (publicKey.excludeCredentials || []).map((x) => ({
+    ...x,
+    id: base64UrlToBuffer(x.id),
+  }));
+
+  // Ask for a discoverable credential so it can be used without typing an username
+  publicKey.authenticatorSelection = {
+    ...publicKey.authenticatorSelection,
+    residentKey: "required",
+    requireResidentKey: true,
+  };
+
+  const credential = await navigator.credentials.create({ publicKey });
+
+  await postJson("/api/auth/passkey/register/finish", {
+    name,
+    credential: {
+      id: credential.id,
+      rawId: bufferToBase64Url(credential.rawId),
+      type: credential.type,
+      extensions: credential.getClientExtensionResults(),
+      response: {
+        attestationObject: bufferToBase64Url(credential.response.attestationObject),
+        clientDataJSON: bufferToBase64Url(credential.response.clientDataJSON),
+      },
+    },
+  });
+}
+
+async function loginWithPasskey() {
+  const rememberMe = document.getElementById("remember-me")?.checked === true;
+  const response = await postJson(`/api/auth/passkey/login/start?remember_me=${rememberMe}`);
+  const { publicKey } = await response.json();
+
+  publicKey.challenge = base64UrlToBuffer(publicKey.challenge);
+  publicKey.allowCredentials = (publicKey.allowCredentials || []).map((x) => ({
+    ...x,
+    id: base64UrlToBuffer(x.id),
+  }));
+
+  const credential = await navigator.credentials.get({ publicKey });
+  const userHandle = credential.response.userHandle;
+
+  const finishResponse = await postJson("/api/auth/passkey/login/finish", {
+    id: credential.id,
+    rawId: bufferToBase64Url(credential.rawId),
+    type: credential.type,
+    extensions: credential.getClientExtensionResults(),
+    response: {
+      authenticatorData: bufferToBase64Url(credential.response.authenticatorData),
+      clientDataJSON: bufferToBase64Url(credential.response.clientDataJSON),
+      signature: bufferToBase64Url(credential.response.signature),
+      userHandle: userHandle ? bufferToBase64Url(userHandle) : null,
+    },
+  });
+
+  // Where to go next, the login page if the user is disabled
+  const { redirect_to } = await finishResponse.json();
+  return redirect_to;
+}
+
+document.addEventListener("DOMContentLoaded", () => {
+  const loginButton = document.getElementById("passkey-login");
+  if (loginButton) {
+    loginButton.addEventListener("click", async () => {
+      try {
+        window.location.href = await loginWithPasskey();
+      } catch (err) {
+        console.error(err);
+        alert("Failed to sign in with a passkey");
+      }
+    });
+  }
+
+  const registerForm = document.getElementById("passkey-register");
+  if (registerForm) {
+    registerForm.addEventListener("submit", async (event) => {
+      event.preventDefault();
+      try {
+        await registerPasskey(new FormData(registerForm).get("name"));
+        window.location.reload();
+      } catch (err) {
+        console.error(err);
+        alert("Failed to register the passkey");
+      }
+    });
+  }
+
+  if (!window.PublicKeyCredential) {
+    document.querySelectorAll("[data-passkey]").forEach((x) => x.classList.add("hidden"));
+  }
+});
@@ -0,0 +1,26 @@
+// Carries the "keep me signed in" choice of the login page to each of the sign in methods.
+
+function updateRememberMe(checked) {
+  const value = checked ? "true" : "false";
+
+  document.querySelectorAll("input[data-remember-me]").forEach((x) => {
+    x.value = value;
+  });
+
+  document.querySelectorAll("a[data-remember-me]").forEach((x) => {
+    const url = new URL(x.href);
+    url.searchParams.set("remember_me", value);
+    x.href = url.toString();
+  });
+}
+
+document.addEventListener("DOMContentLoaded", () => {
+  const checkbox = document.getElementById("remember-me");
+  if (!checkbox) {
+    return;
+  }
+
+  // The browser may restore the checkbox when going back to the page
+  checkbox.addEventListener("change", () => updateRememberMe(checkbox.checked));
+  updateRememberMe(checkbox.checked);
+});
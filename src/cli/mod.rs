@@ -0,0 +1,423 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use clap::{Parser, Subcommand};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    db::DbPool,
+    models::{AuthProvider, UserSummary},
+    session_store::{SessionStoreConfig, SharedSessionStore},
+};
+
+/// The commands of the server binary, to manage a deployment from a shell.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Sign in with oauth providers, and manage the users from the command line",
+    long_about = "Sign in with oauth providers, and manage the users from the command line.\n\n\
+        A running server keeps the users and roles of the recent sessions in its cache, so the changes \
+        made by these commands, like disabling an user or revoking its sessions, may take up to \
+        `SESSION_CACHE_TTL_SECONDS` to apply to it."
+)]
+pub struct Cli {
+    /// Starts the server if not set.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Starts the server.
+    Serve,
+
+    #[command(flatten)]
+    Admin(AdminCommand),
+}
+
+/// The commands that manage the deployment instead of starting the server.
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// Applies the migrations that were not applied yet, creating the sqlite database if needed.
+    Migrate,
+
+    /// Lists, inspects, disables and deletes users.
+    #[command(subcommand)]
+    Users(UsersCommand),
+
+    /// Signs users out.
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+
+    /// Gives roles to users.
+    #[command(subcommand)]
+    Roles(RolesCommand),
+
+    /// Validates the settings.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UsersCommand {
+    /// Lists the users, the most recent first.
+    List {
+        /// Only the users which username or account id contains this text.
+        #[arg(long)]
+        search: Option<String>,
+        #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(i64).range(1..=1000))]
+        limit: i64,
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(i64).range(1..))]
+        page: i64,
+    },
+
+    /// Displays an user with its roles and sessions.
+    Show { user: UserRef },
+
+    /// Disables an user and signs it out everywhere.
+    Disable { user: UserRef },
+
+    /// Enables an user that was disabled.
+    Enable { user: UserRef },
+
+    /// Deletes an user and all its data.
+    Delete {
+        user: UserRef,
+        /// Confirms the user should be deleted, this can't be undone.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// Deletes all the sessions of an user.
+    Revoke {
+        #[arg(long)]
+        user: UserRef,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RolesCommand {
+    /// Gives a role to an user.
+    Grant { user: UserRef, role: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Loads the settings and reports all the problems found.
+    Check,
+}
+
+/// An user given by its id, or by its provider account like `github:1234`.
+#[derive(Debug, Clone)]
+pub enum UserRef {
+    Id(Uuid),
+    Account(AuthProvider, String),
+}
+
+impl FromStr for UserRef {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(user_id) = Uuid::parse_str(value) {
+            return Ok(UserRef::Id(user_id));
+        }
+
+        AuthProvider::parse_account(value)
+            .map(|(provider, account_id)| UserRef::Account(provider, account_id))
+            .ok_or_else(|| "expected an user id or 'provider:account_id'".to_owned())
+    }
+}
+
+/// Runs a command with the settings of the server, the output is written to stdout.
+pub async fn run(command: AdminCommand, config: Config) -> Result<(), anyhow::Error> {
+    match command {
+        AdminCommand::Migrate => migrate(&config).await,
+        AdminCommand::Users(command) => users(command, &config).await,
+        AdminCommand::Sessions(SessionsCommand::Revoke { user }) => {
+            revoke_sessions(&user, &config).await
+        }
+        AdminCommand::Roles(RolesCommand::Grant { user, role }) => {
+            grant_role(&user, &role, &config).await
+        }
+        AdminCommand::Config(ConfigCommand::Check) => {
+            check_config(&config);
+            Ok(())
+        }
+    }
+}
+
+async fn migrate(config: &Config) -> Result<(), anyhow::Error> {
+    let pool = DbPool::connect(config.database_url.expose(), true)
+        .await
+        .context("Failed to connect to database")?;
+
+    pool.run_migrations()
+        .await
+        .context("Failed to run database migrations")?;
+
+    println!("The database is up to date");
+    Ok(())
+}
+
+async fn users(command: UsersCommand, config: &Config) -> Result<(), anyhow::Error> {
+    let pool = connect(config).await?;
+
+    match command {
+        UsersCommand::List {
+            search,
+            limit,
+            page,
+        } => {
+            let offset = (page - 1)
+                .checked_mul(limit)
+                .context("The page is past the last user")?;
+            let (users, total) = crate::db::search_users(&pool, search.as_deref(), limit, offset)
+                .await
+                .context("Failed to search users")?;
+
+            println!(
+                "{:<36}  {:<8}  {:<24}  {:<24}  {:<16}  STATUS",
+                "ID", "PROVIDER", "ACCOUNT ID", "USERNAME", "LAST LOGIN"
+            );
+
+            for user in users {
+                println!(
+                    "{:<36}  {:<8}  {:<24}  {:<24}  {:<16}  {}",
+                    user.id,
+                    user.provider.to_string(),
+                    user.account_id,
+                    user.username,
+                    format_date(user.last_login_at),
+                    if user.disabled { "disabled" } else { "enabled" }
+                );
+            }
+
+            let pages = (total + limit - 1) / limit;
+            println!("\nPage {page} of {} ({total} in total)", pages.max(1));
+        }
+        UsersCommand::Show { user } => {
+            let user = find_user(&pool, &user).await?;
+            let roles = crate::db::get_user_roles(&pool, user.id)
+                .await
+                .context("Failed to get user roles")?;
+
+            println!("Id:          {}", user.id);
+            println!("Provider:    {}", user.provider);
+            println!("Account id:  {}", user.account_id);
+            println!("Username:    {}", user.username);
+            println!("Created:     {}", format_date(user.created_at));
+            println!("Last login:  {}", format_date(user.last_login_at));
+            println!("Disabled:    {}", if user.disabled { "yes" } else { "no" });
+
+            let role_names = roles.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+            println!("Roles:       {}", role_names.join(", "));
+
+            let Some(session_store) = session_store(config, &pool).await? else {
+                println!("Sessions:    kept in the memory of the server");
+                return Ok(());
+            };
+
+            let sessions = session_store
+                .list_for_user(user.id, None)
+                .await
+                .context("Failed to get user sessions")?;
+
+            println!("Sessions:    {}", sessions.len());
+            for session in sessions {
+                println!(
+                    "  {:>6}  {}  last seen {}  {}  {}",
+                    session.handle,
+                    format_date(Some(session.created_at)),
+                    format_date(session.last_seen_at),
+                    session.ip_address.as_deref().unwrap_or("unknown ip"),
+                    session.device()
+                );
+            }
+        }
+        UsersCommand::Disable { user } => {
+            let user = find_user(&pool, &user).await?;
+            crate::db::set_user_disabled(&pool, user.id, true)
+                .await
+                .context("Failed to update user")?;
+
+            // Disabling an user also signs it out everywhere, the sessions in memory are rejected by the server
+            if let Some(session_store) = session_store(config, &pool).await? {
+                session_store
+                    .delete_for_user(user.id, None)
+                    .await
+                    .context("Failed to delete user sessions")?;
+            }
+
+            println!("Disabled user '{}' ({})", user.username, user.id);
+        }
+        UsersCommand::Enable { user } => {
+            let user = find_user(&pool, &user).await?;
+            crate::db::set_user_disabled(&pool, user.id, false)
+                .await
+                .context("Failed to update user")?;
+
+            println!("Enabled user '{}' ({})", user.username, user.id);
+        }
+        UsersCommand::Delete { user, yes } => {
+            let user = find_user(&pool, &user).await?;
+            if !yes {
+                anyhow::bail!(
+                    "Deleting user '{}' ({}) can't be undone, pass --yes to confirm",
+                    user.username,
+                    user.id
+                );
+            }
+
+            if let Some(session_store) = session_store(config, &pool).await? {
+                session_store
+                    .delete_for_user(user.id, None)
+                    .await
+                    .context("Failed to delete user sessions")?;
+            }
+
+            crate::db::delete_user(&pool, user.id)
+                .await
+                .context("Failed to delete user")?;
+
+            println!("Deleted user '{}' ({})", user.username, user.id);
+        }
+    }
+
+    Ok(())
+}
+
+async fn revoke_sessions(user: &UserRef, config: &Config) -> Result<(), anyhow::Error> {
+    let pool = connect(config).await?;
+    let user = find_user(&pool, user).await?;
+
+    let Some(session_store) = session_store(config, &pool).await? else {
+        anyhow::bail!(
+            "The sessions are kept in the memory of the server, they can't be revoked from the command line"
+        );
+    };
+
+    let deleted = session_store
+        .delete_for_user(user.id, None)
+        .await
+        .context("Failed to delete user sessions")?;
+
+    println!(
+        "Revoked {deleted} sessions of user '{}' ({})",
+        user.username, user.id
+    );
+
+    Ok(())
+}
+
+async fn grant_role(user: &UserRef, role_name: &str, config: &Config) -> Result<(), anyhow::Error> {
+    let pool = connect(config).await?;
+    let user = find_user(&pool, user).await?;
+
+    let granted = crate::db::grant_user_role(&pool, user.id, role_name)
+        .await
+        .context("Failed to grant role")?;
+
+    if !granted {
+        let roles = crate::db::get_roles(&pool)
+            .await
+            .context("Failed to get roles")?;
+
+        let role_names = roles.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        anyhow::bail!(
+            "The role '{role_name}' doesn't exist, the roles are: {}",
+            role_names.join(", ")
+        );
+    }
+
+    println!(
+        "Gave the '{role_name}' role to user '{}' ({})",
+        user.username, user.id
+    );
+
+    Ok(())
+}
+
+/// The settings were already validated when they were loaded.
+fn check_config(config: &Config) {
+    // The type is needed when the build has no providers
+    let providers: Vec<(&str, bool)> = vec![
+        #[cfg(feature = "google")]
+        ("google", config.google.is_some()),
+        #[cfg(feature = "github")]
+        ("github", config.github.is_some()),
+        #[cfg(feature = "discord")]
+        ("discord", config.discord.is_some()),
+        #[cfg(feature = "oidc")]
+        ("oidc", config.oidc.is_some()),
+        #[cfg(feature = "mock")]
+        ("mock", config.mock_auth),
+    ];
+
+    let providers = providers
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    println!("The configuration is valid");
+    println!("Base url:       {}", config.base_url);
+    if providers.is_empty() {
+        println!("Providers:      none");
+    } else {
+        println!("Providers:      {}", providers.join(", "));
+    }
+    println!("Session store:  {:?}", config.session_store);
+}
+
+async fn connect(config: &Config) -> Result<DbPool, anyhow::Error> {
+    DbPool::connect(config.database_url.expose(), false)
+        .await
+        .context("Failed to connect to database")
+}
+
+/// Returns the store of the sessions, unless they are kept in the memory of the server.
+async fn session_store(
+    config: &Config,
+    pool: &DbPool,
+) -> Result<Option<SharedSessionStore>, anyhow::Error> {
+    if let SessionStoreConfig::Memory = config.session_store {
+        return Ok(None);
+    }
+
+    crate::session_store::create_session_store(&config.session_store, pool.clone())
+        .await
+        .map(Some)
+        .context("Failed to create session store")
+}
+
+async fn find_user(pool: &DbPool, user: &UserRef) -> Result<UserSummary, anyhow::Error> {
+    let user_id = match user {
+        UserRef::Id(user_id) => *user_id,
+        UserRef::Account(provider, account_id) => {
+            crate::db::get_user_by_account_id(pool, *provider, account_id.clone())
+                .await
+                .context("Failed to get user")?
+                .with_context(|| {
+                    format!("There is no user with the account '{provider}:{account_id}'")
+                })?
+                .id
+        }
+    };
+
+    crate::db::get_user_summary(pool, user_id)
+        .await
+        .context("Failed to get user")?
+        .with_context(|| format!("There is no user with the id '{user_id}'"))
+}
+
+fn format_date(date: Option<NaiveDateTime>) -> String {
+    match date {
+        Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+        None => "never".to_owned(),
+    }
+}
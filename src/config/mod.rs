@@ -0,0 +1,523 @@
+use std::{collections::HashMap, net::SocketAddr, path::Path, sync::Arc};
+
+use anyhow::Context;
+use reqwest::Url;
+
+#[cfg(feature = "oidc")]
+use crate::providers::OidcConfig;
+use crate::{
+    mailer::MailerConfig,
+    models::AuthProvider,
+    routes::LocalAuthConfig,
+    server::{SessionCacheConfig, SessionConfig, TrustedProxies},
+    session_store::SessionStoreConfig,
+    tasks::SweeperConfig,
+};
+
+/// The config shared by the routes, it's added as an extension.
+pub type SharedAuthConfig = Arc<AuthConfig>;
+
+/// The settings of the server, they are loaded and validated once when it starts.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    /// Where the users reach the server, used for the oauth redirects, the sign-in links and the passkeys.
+    pub base_url: String,
+    pub database_url: Secret,
+    pub auto_migrate: bool,
+    #[cfg(feature = "google")]
+    pub google: Option<OAuthClientConfig>,
+    #[cfg(feature = "github")]
+    pub github: Option<OAuthClientConfig>,
+    #[cfg(feature = "discord")]
+    pub discord: Option<OAuthClientConfig>,
+    #[cfg(feature = "oidc")]
+    pub oidc: Option<OidcConfig>,
+    /// Whether anyone can sign in with any username, only for development.
+    #[cfg(feature = "mock")]
+    pub mock_auth: bool,
+    /// The provider accounts that are given the admin role when they sign in.
+    pub admin_accounts: Vec<(AuthProvider, String)>,
+    pub local_auth: LocalAuthConfig,
+    pub mailer: Option<MailerConfig>,
+    pub session: SessionConfig,
+    pub session_cache: SessionCacheConfig,
+    pub session_store: SessionStoreConfig,
+    pub sweeper: SweeperConfig,
+    pub trusted_proxies: TrustedProxies,
+    /// Where the Prometheus metrics are served, apart from the app so they are not public.
+    pub metrics_address: Option<SocketAddr>,
+}
+
+impl Config {
+    /// Loads the settings from the environment and the toml file in `CONFIG_FILE`, if any.
+    /// The error lists all the settings that are missing or invalid.
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let config_file = std::env::var("CONFIG_FILE").ok();
+        let mut reader = ConfigReader::new(config_file.as_deref().map(Path::new))?;
+        let config = Config::from_config(&mut reader);
+        reader.finish()?;
+        Ok(config)
+    }
+
+    fn from_config(reader: &mut ConfigReader) -> Self {
+        let host = reader.required("HOST").unwrap_or_default();
+        let port = reader
+            .required("PORT")
+            .and_then(|_| reader.parse_with("PORT", "a port number", |x| x.parse::<u16>().ok()))
+            .unwrap_or_default();
+
+        let base_url = reader.required("BASE_URL").unwrap_or_default();
+        let has_host = Url::parse(&base_url).is_ok_and(|x| x.host().is_some());
+        if !base_url.is_empty() && !has_host {
+            reader.error(format!(
+                "Invalid 'BASE_URL', expected an url with a host: {base_url}"
+            ));
+        }
+
+        let database_url = reader
+            .required_secret("DATABASE_URL")
+            .unwrap_or_else(|| Secret::new(String::new()));
+        let auto_migrate = reader.flag("AUTO_MIGRATE", true);
+
+        // The settings of the providers that were left out of the build are a mistake, not ignored
+        #[cfg(not(feature = "google"))]
+        reader.not_enabled("GOOGLE_CLIENT_ID", "google");
+        #[cfg(not(feature = "github"))]
+        reader.not_enabled("GITHUB_CLIENT_ID", "github");
+        #[cfg(not(feature = "discord"))]
+        reader.not_enabled("DISCORD_CLIENT_ID", "discord");
+        #[cfg(not(feature = "oidc"))]
+        reader.not_enabled("OIDC_CLIENT_ID", "oidc");
+        #[cfg(not(feature = "mock"))]
+        reader.not_enabled("MOCK_AUTH_ENABLED", "mock");
+
+        Config {
+            host,
+            port,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            database_url,
+            auto_migrate,
+            #[cfg(feature = "google")]
+            google: OAuthClientConfig::from_config(reader, "GOOGLE"),
+            #[cfg(feature = "github")]
+            github: OAuthClientConfig::from_config(reader, "GITHUB"),
+            #[cfg(feature = "discord")]
+            discord: OAuthClientConfig::from_config(reader, "DISCORD"),
+            #[cfg(feature = "oidc")]
+            oidc: OidcConfig::from_config(reader),
+            #[cfg(feature = "mock")]
+            mock_auth: reader.flag("MOCK_AUTH_ENABLED", false),
+            admin_accounts: admin_accounts_from_config(reader),
+            local_auth: LocalAuthConfig::from_config(reader),
+            mailer: MailerConfig::from_config(reader),
+            session: SessionConfig::from_config(reader),
+            session_cache: SessionCacheConfig::from_config(reader),
+            session_store: SessionStoreConfig::from_config(reader),
+            sweeper: SweeperConfig::from_config(reader),
+            trusted_proxies: TrustedProxies::from_config(reader),
+            metrics_address: reader.parse_with(
+                "METRICS_ADDRESS",
+                "an address like '127.0.0.1:9000'",
+                |x| x.parse::<SocketAddr>().ok(),
+            ),
+        }
+    }
+
+    /// Returns the settings used by the auth routes.
+    pub fn auth_config(&self) -> AuthConfig {
+        AuthConfig {
+            base_url: self.base_url.clone(),
+            admin_accounts: self.admin_accounts.clone(),
+            local_auth: self.local_auth,
+            mailer: self.mailer.clone(),
+            session: self.session,
+            session_cache: self.session_cache,
+            trusted_proxies: self.trusted_proxies.clone(),
+        }
+    }
+}
+
+/// The settings of the auth routes, the server builds them from its [`Config`] and
+/// other applications can start from [`AuthConfig::new`].
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Where the users reach the server, used for the oauth redirects, the sign-in links and the passkeys.
+    pub base_url: String,
+    /// The provider accounts that are given the admin role when their account is created.
+    pub admin_accounts: Vec<(AuthProvider, String)>,
+    pub local_auth: LocalAuthConfig,
+    /// How the sign-in links are sent, the email sign-in is disabled without it.
+    pub mailer: Option<MailerConfig>,
+    pub session: SessionConfig,
+    pub session_cache: SessionCacheConfig,
+    pub trusted_proxies: TrustedProxies,
+}
+
+impl AuthConfig {
+    /// The default settings, the email sign-in is disabled and no proxy is trusted.
+    pub fn new(base_url: &str) -> Self {
+        AuthConfig {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            admin_accounts: Vec::new(),
+            local_auth: LocalAuthConfig::default(),
+            mailer: None,
+            session: SessionConfig::default(),
+            session_cache: SessionCacheConfig::default(),
+            trusted_proxies: TrustedProxies::default(),
+        }
+    }
+}
+
+/// The credentials of an oauth app, the provider can't be used to sign in if they are not set.
+#[derive(Debug, Clone)]
+pub struct OAuthClientConfig {
+    pub client_id: String,
+    pub client_secret: Secret,
+}
+
+impl OAuthClientConfig {
+    /// Reads `{provider}_CLIENT_ID` and `{provider}_CLIENT_SECRET`, which must be set together.
+    pub fn from_config(reader: &mut ConfigReader, provider: &str) -> Option<Self> {
+        let id_name = format!("{provider}_CLIENT_ID");
+        let secret_name = format!("{provider}_CLIENT_SECRET");
+        let client_id = reader.get(&id_name);
+        let client_secret = reader.secret(&secret_name);
+
+        match (client_id, client_secret) {
+            (Some(client_id), Some(client_secret)) => Some(OAuthClientConfig {
+                client_id,
+                client_secret,
+            }),
+            (None, None) => None,
+            (Some(_), None) => {
+                reader.error(format!("'{secret_name}' is not set, but '{id_name}' is"));
+                None
+            }
+            (None, Some(_)) => {
+                reader.error(format!("'{id_name}' is not set, but '{secret_name}' is"));
+                None
+            }
+        }
+    }
+}
+
+/// Reads `ADMIN_ACCOUNTS`, a comma separated list of `provider:account_id` like `github:1234,local:admin`.
+fn admin_accounts_from_config(reader: &mut ConfigReader) -> Vec<(AuthProvider, String)> {
+    let Some(value) = reader.get("ADMIN_ACCOUNTS") else {
+        return Vec::new();
+    };
+
+    let mut admin_accounts = Vec::new();
+    for entry in value.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        match AuthProvider::parse_account(entry) {
+            Some(account) => admin_accounts.push(account),
+            None => reader.error(format!(
+                "Invalid 'ADMIN_ACCOUNTS' entry, expected 'provider:account_id': {entry}"
+            )),
+        }
+    }
+
+    admin_accounts
+}
+
+/// A setting that is not displayed in the logs, like a password or a client secret.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret([redacted])")
+    }
+}
+
+/// Reads the settings by name, from the environment or else from the config file,
+/// and keeps the problems found so they are all reported at once.
+///
+/// The tables of the config file are joined to the names of their keys, so `port` in
+/// the `[smtp]` table is the `SMTP_PORT` setting. Empty settings are the same as missing.
+#[derive(Debug, Default)]
+pub struct ConfigReader {
+    file: HashMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl ConfigReader {
+    pub fn new(config_file: Option<&Path>) -> Result<Self, anyhow::Error> {
+        let Some(path) = config_file else {
+            return Ok(Default::default());
+        };
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {path:?}"))?;
+        let table = contents
+            .parse::<toml::Table>()
+            .with_context(|| format!("Invalid config file {path:?}"))?;
+
+        let mut file = HashMap::new();
+        flatten_table(None, table, &mut file);
+
+        Ok(ConfigReader {
+            file,
+            errors: Vec::new(),
+        })
+    }
+
+    /// A reader of the given settings, as if they were read from a config file.
+    #[cfg(test)]
+    pub(crate) fn from_settings(settings: &[(&str, &str)]) -> Self {
+        let file = settings
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        ConfigReader {
+            file,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Returns the value of a setting, if it's set.
+    pub fn get(&self, name: &str) -> Option<String> {
+        std::env::var(name)
+            .ok()
+            .or_else(|| self.file.get(name).cloned())
+            .map(|x| x.trim().to_owned())
+            .filter(|x| !x.is_empty())
+    }
+
+    /// Returns the value of a setting, a missing setting is a problem.
+    pub fn required(&mut self, name: &str) -> Option<String> {
+        let value = self.get(name);
+        if value.is_none() {
+            self.error(format!("'{name}' is not set"));
+        }
+
+        value
+    }
+
+    /// Returns a secret, which can also be read from the file in `{name}_FILE`, the file takes precedence.
+    pub fn secret(&mut self, name: &str) -> Option<Secret> {
+        let file_name = format!("{name}_FILE");
+        let Some(path) = self.get(&file_name) else {
+            return self.get(name).map(Secret::new);
+        };
+
+        if self.get(name).is_some() {
+            tracing::warn!("both '{name}' and '{file_name}' are set, '{file_name}' is used");
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(value) => Some(Secret::new(value.trim().to_owned())),
+            Err(err) => {
+                self.error(format!("Failed to read '{file_name}' {path:?}: {err}"));
+                None
+            }
+        }
+    }
+
+    /// Returns a secret, a missing secret is a problem.
+    pub fn required_secret(&mut self, name: &str) -> Option<Secret> {
+        let secret = self.secret(name);
+        if secret.is_none() && self.get(&format!("{name}_FILE")).is_none() {
+            self.error(format!("'{name}' is not set"));
+        }
+
+        secret
+    }
+
+    /// Parses a setting with `parse`, which returns `None` if the value is not the `expected` one.
+    pub fn parse_with<T>(
+        &mut self,
+        name: &str,
+        expected: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Option<T> {
+        let value = self.get(name)?;
+        let parsed = parse(&value);
+        if parsed.is_none() {
+            self.error(format!("Invalid '{name}', expected {expected}: {value}"));
+        }
+
+        parsed
+    }
+
+    /// Returns a `true` or `false` setting.
+    pub fn flag(&mut self, name: &str, default: bool) -> bool {
+        self.parse_with(name, "'true' or 'false'", |x| match x {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        })
+        .unwrap_or(default)
+    }
+
+    /// A setting of a cargo feature that is not enabled is a problem.
+    pub fn not_enabled(&mut self, name: &str, feature: &str) {
+        if self.get(name).is_some() {
+            self.error(format!(
+                "'{name}' is set, but the '{feature}' feature is not enabled"
+            ));
+        }
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.errors.push(message.into());
+    }
+
+    /// Returns an error with all the problems found.
+    pub fn finish(self) -> Result<(), anyhow::Error> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        let problems = self
+            .errors
+            .iter()
+            .map(|x| format!("  - {x}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        anyhow::bail!("Invalid configuration:\n{problems}")
+    }
+}
+
+fn flatten_table(prefix: Option<&str>, table: toml::Table, settings: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let name = match prefix {
+            Some(prefix) => format!("{prefix}_{}", key.to_uppercase()),
+            None => key.to_uppercase(),
+        };
+
+        let value = match value {
+            toml::Value::Table(table) => {
+                flatten_table(Some(&name), table, settings);
+                continue;
+            }
+            toml::Value::Array(values) => values
+                .into_iter()
+                .map(value_to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            value => value_to_string(value),
+        };
+
+        settings.insert(name, value);
+    }
+}
+
+fn value_to_string(value: toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value,
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A file in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("config-test-{}", uuid::Uuid::new_v4()));
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn reads_trimmed_secrets_from_files() {
+        let secret_file = TempFile::new("  file-secret\n");
+        let path = secret_file.0.to_str().unwrap();
+
+        let mut reader = ConfigReader::from_settings(&[("CONFIG_TEST_SECRET_FILE", path)]);
+        let secret = reader.secret("CONFIG_TEST_SECRET").unwrap();
+        assert_eq!(secret.expose(), "file-secret");
+
+        // The file takes precedence over the setting
+        let mut reader = ConfigReader::from_settings(&[
+            ("CONFIG_TEST_SECRET", "setting-secret"),
+            ("CONFIG_TEST_SECRET_FILE", path),
+        ]);
+        let secret = reader.secret("CONFIG_TEST_SECRET").unwrap();
+        assert_eq!(secret.expose(), "file-secret");
+        assert!(reader.finish().is_ok());
+
+        let mut reader =
+            ConfigReader::from_settings(&[("CONFIG_TEST_SECRET_FILE", "/does/not/exist")]);
+        assert!(reader.required_secret("CONFIG_TEST_SECRET").is_none());
+        assert!(reader.finish().is_err());
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() {
+        let config_file = TempFile::new(
+            r#"
+                config_test_overridden = "file"
+                config_test_kept = "file"
+            "#,
+        );
+
+        std::env::set_var("CONFIG_TEST_OVERRIDDEN", "env");
+        let reader = ConfigReader::new(Some(&config_file.0)).unwrap();
+        assert_eq!(reader.get("CONFIG_TEST_OVERRIDDEN").as_deref(), Some("env"));
+        assert_eq!(reader.get("CONFIG_TEST_KEPT").as_deref(), Some("file"));
+    }
+
+    #[test]
+    fn flattens_the_tables() {
+        let config_file = TempFile::new(
+            r#"
+                port = 5000
+                config_test_empty = ""
+                trusted_proxies = ["10.0.0.0/8", "192.168.0.1"]
+
+                [config_test_smtp]
+                host = "smtp.example.com"
+
+                [config_test_smtp.tls]
+                enabled = true
+            "#,
+        );
+
+        let reader = ConfigReader::new(Some(&config_file.0)).unwrap();
+        assert_eq!(reader.file["PORT"], "5000");
+        assert_eq!(reader.file["TRUSTED_PROXIES"], "10.0.0.0/8,192.168.0.1");
+        assert_eq!(reader.file["CONFIG_TEST_SMTP_HOST"], "smtp.example.com");
+        assert_eq!(reader.file["CONFIG_TEST_SMTP_TLS_ENABLED"], "true");
+        assert_eq!(reader.get("CONFIG_TEST_EMPTY"), None);
+    }
+
+    #[test]
+    fn reports_all_the_errors() {
+        let mut reader = ConfigReader::from_settings(&[("CONFIG_TEST_FLAG", "yes")]);
+        reader.required("CONFIG_TEST_MISSING");
+        reader.flag("CONFIG_TEST_FLAG", false);
+
+        let err = reader.finish().unwrap_err().to_string();
+        assert!(err.contains("'CONFIG_TEST_MISSING' is not set"), "{err}");
+        assert!(err.contains("Invalid 'CONFIG_TEST_FLAG'"), "{err}");
+    }
+}
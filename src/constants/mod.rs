@@ -3,7 +3,41 @@ use std::time::Duration;
 pub const COOKIE_AUTH_SESSION: &str = "auth_session";
 pub const COOKIE_AUTH_CSRF_STATE: &str = "auth_csrf_state";
 pub const COOKIE_AUTH_CODE_VERIFIER: &str = "auth_code_verifier";
+pub const COOKIE_AUTH_PASSKEY_CHALLENGE: &str = "auth_passkey_challenge";
+pub const COOKIE_AUTH_REMEMBER_ME: &str = "auth_remember_me";
 
 //
 pub const COOKIE_THEME: &str = "theme";
-pub const SESSION_DURATION: Duration = Duration::from_millis(1000 * 60 * 60 * 24); // 1 day
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_millis(1000 * 60 * 60 * 24); // 1 day
+pub const SESSION_PERSISTENT_TIMEOUT: Duration = Duration::from_millis(1000 * 60 * 60 * 24 * 30); // 30 days
+pub const SESSION_ABSOLUTE_TIMEOUT: Duration = Duration::from_millis(1000 * 60 * 60 * 24 * 7); // 7 days
+pub const SESSION_PERSISTENT_ABSOLUTE_TIMEOUT: Duration =
+    Duration::from_millis(1000 * 60 * 60 * 24 * 90); // 90 days
+pub const SESSION_CACHE_TTL: Duration = Duration::from_millis(1000 * 60); // 1 minute
+pub const SESSION_CACHE_SIZE: usize = 10_000;
+pub const SESSION_LAST_SEEN_UPDATE_INTERVAL: Duration = Duration::from_millis(1000 * 60 * 5); // 5 minutes
+pub const SECOND_FACTOR_PENDING_DURATION: Duration = Duration::from_millis(1000 * 60 * 10); // 10 minutes
+pub const SECOND_FACTOR_MAX_ATTEMPTS: i64 = 5;
+pub const LOGIN_MAX_FAILED_ATTEMPTS: i64 = 5;
+pub const LOGIN_LOCKOUT_DURATION: Duration = Duration::from_millis(1000 * 60 * 15); // 15 minutes
+pub const RECOVERY_CODE_COUNT: usize = 10;
+pub const RECOVERY_CODE_LOW_THRESHOLD: i64 = 3;
+
+pub const EMAIL_LOGIN_TOKEN_DURATION: Duration = Duration::from_millis(1000 * 60 * 15); // 15 minutes
+pub const EMAIL_LOGIN_RATE_LIMIT_WINDOW: Duration = Duration::from_millis(1000 * 60 * 15); // 15 minutes
+pub const EMAIL_LOGIN_MAX_PER_ADDRESS: u32 = 3;
+pub const EMAIL_LOGIN_MAX_PER_IP: u32 = 10;
+pub const GUEST_LOGIN_RATE_LIMIT_WINDOW: Duration = Duration::from_millis(1000 * 60 * 60); // 1 hour
+pub const GUEST_LOGIN_MAX_PER_IP: u32 = 10;
+pub const RATE_LIMIT_MAX_KEYS: usize = 100_000;
+pub const PASSKEY_CHALLENGE_DURATION: Duration = Duration::from_millis(1000 * 60 * 5); // 5 minutes
+
+pub const ADMIN_USERS_PAGE_SIZE: i64 = 20;
+
+pub const SWEEP_INTERVAL: Duration = Duration::from_millis(1000 * 60 * 5); // 5 minutes
+pub const SWEEP_BATCH_SIZE: i64 = 500;
+pub const GUEST_PRUNE_GRACE_PERIOD: Duration = Duration::from_millis(1000 * 60 * 60); // 1 hour
+
+pub const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_millis(1000 * 5); // 5 seconds
+pub const HTTP_REQUEST_TIMEOUT: Duration = Duration::from_millis(1000 * 15); // 15 seconds
+pub const HTTP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
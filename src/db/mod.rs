@@ -1,160 +1,383 @@
-use std::{str::FromStr, time::Duration};
+use std::{collections::HashSet, str::FromStr, time::Duration};
 
-use crate::models::{AuthProvider, User, UserSession};
-use sqlx::SqlitePool;
+use chrono::NaiveDateTime;
+use sqlx::migrate::MigrateError;
+#[cfg(feature = "postgres")]
+use sqlx::postgres::PgPool;
+#[cfg(feature = "sqlite")]
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
 use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
 
-pub async fn get_user_by_account_id(
-    pool: &SqlitePool,
-    provider: AuthProvider,
-    account_id: String,
-) -> Result<Option<User>, anyhow::Error> {
-    let provider = provider.to_string();
-    let user = sqlx::query_as!(
-        User,
-        r#"
-            SELECT id as "id: uuid::Uuid", account_id, provider, username, image_url
-            FROM user 
-            WHERE account_id = ?1 AND provider = ?2
-        "#,
-        account_id,
-        provider
-    )
-    .fetch_optional(pool)
-    .await?;
-
-    Ok(user)
+use crate::models::{
+    AuthProvider, Permission, Role, User, UserCredential, UserPasskey, UserSession,
+    UserSessionInfo, UserSummary, UserTotp,
+};
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("At least one of the 'sqlite' and 'postgres' features must be enabled");
+
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+/// The database of the users, the backend is selected by the scheme of `DATABASE_URL`.
+#[derive(Debug, Clone)]
+pub enum DbPool {
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(PgPool),
 }
 
-pub async fn get_user_by_session_id(
-    pool: &SqlitePool,
-    session_id: &str,
-) -> Result<Option<User>, anyhow::Error> {
-    let session_id = Uuid::from_str(session_id)?;
-    let user = sqlx::query_as!(
-        User,
-        r#"
-            SELECT user.id as "id: uuid::Uuid", account_id, provider, username, image_url
-            FROM user
-            LEFT JOIN user_session AS session ON session.user_id = user.id
-            WHERE session.id = ?1
-        "#,
-        session_id
-    )
-    .fetch_optional(pool)
-    .await?;
-
-    if let Some(user) = &user {
-        let deleted = delete_expired_user_sessions(pool, user.id).await?;
-        if deleted > 0 {
-            tracing::info!(
-                "{deleted:?} expired sessions where deleted for user '{}'",
-                user.id
-            )
+impl DbPool {
+    /// Connects to a `sqlite:` or `postgres:` database, the sqlite database file is created
+    /// if it doesn't exist and `create_if_missing` is set.
+    pub async fn connect(url: &str, create_if_missing: bool) -> Result<Self, anyhow::Error> {
+        if url.starts_with("sqlite:") {
+            Self::connect_sqlite(url, create_if_missing).await
+        } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            Self::connect_postgres(url).await
+        } else {
+            anyhow::bail!("Unsupported database url, expected a 'sqlite:' or 'postgres:' url");
         }
     }
 
-    Ok(user)
-}
+    #[cfg(feature = "sqlite")]
+    async fn connect_sqlite(url: &str, create_if_missing: bool) -> Result<Self, anyhow::Error> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(create_if_missing);
+        Ok(DbPool::Sqlite(SqlitePool::connect_with(options).await?))
+    }
 
-pub async fn create_user(
-    pool: &SqlitePool,
-    account_id: String,
-    provider: AuthProvider,
-    username: String,
-    image_url: Option<String>,
-) -> Result<User, anyhow::Error> {
-    let id = Uuid::new_v4();
-    let provider = provider.to_string();
-    let new_user = sqlx::query_as!(
-        User,
-        r#"
-            INSERT INTO user (id, account_id, provider, username, image_url) 
-            VALUES (?1, ?2, ?3, ?4, ?5) 
-            RETURNING id as "id: uuid::Uuid", account_id, provider, username, image_url
-        "#,
-        id,
-        account_id,
-        provider,
-        username,
-        image_url
-    )
-    .fetch_one(pool)
-    .await?;
-
-    Ok(new_user)
+    #[cfg(not(feature = "sqlite"))]
+    async fn connect_sqlite(_url: &str, _create_if_missing: bool) -> Result<Self, anyhow::Error> {
+        anyhow::bail!("SQLite databases are not supported, the 'sqlite' feature is not enabled")
+    }
+
+    #[cfg(feature = "postgres")]
+    async fn connect_postgres(url: &str) -> Result<Self, anyhow::Error> {
+        Ok(DbPool::Postgres(PgPool::connect(url).await?))
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    async fn connect_postgres(_url: &str) -> Result<Self, anyhow::Error> {
+        anyhow::bail!(
+            "PostgreSQL databases are not supported, the 'postgres' feature is not enabled"
+        )
+    }
+
+    /// Applies the migrations embedded in the binary that were not applied yet,
+    /// `migrations` for sqlite and `migrations_postgres` for postgres.
+    pub async fn run_migrations(&self) -> Result<(), anyhow::Error> {
+        let result = match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => sqlx::migrate!("./migrations").run(pool).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => sqlx::migrate!("./migrations_postgres").run(pool).await,
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(MigrateError::VersionMismatch(version)) => Err(anyhow::anyhow!(
+                "Migration {version} was modified after it was applied to the database, \
+                restore the original migration and add the changes in a new one"
+            )),
+            Err(err) => Err(err.into()),
+        }
+    }
 }
 
-pub async fn create_user_session(
-    pool: &SqlitePool,
-    user_id: Uuid,
-    session_duration: Duration,
-) -> Result<UserSession, anyhow::Error> {
-    let session_id = Uuid::new_v4();
-    let created_at = chrono::offset::Utc::now().naive_utc();
-    let expires_at = created_at + session_duration;
-
-    sqlx::query!(
-        r#"
-            INSERT INTO user_session (id, user_id, created_at, expires_at)
-            VALUES (?1, ?2, ?3, ?4)
-        "#,
-        session_id,
-        user_id,
-        created_at,
-        expires_at
-    )
-    .execute(pool)
-    .await?;
-
-    let user_session = sqlx::query_as!(
-        UserSession,
-        r#"
-            SELECT 
-                id as "id: uuid::Uuid",
-                user_id as "user_id: uuid::Uuid",
-                created_at as "created_at: _",
-                expires_at as "expires_at: _" 
-            FROM user_session
-            WHERE id = ?1
-        "#,
-        session_id
-    )
-    .fetch_one(pool)
-    .await?;
-
-    Ok(user_session)
+// Each function is implemented by both backends, which take the pool of their database
+macro_rules! db_functions {
+    ($(
+        $(#[$attr:meta])*
+        pub async fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty;
+    )*) => {
+        $(
+            $(#[$attr])*
+            pub async fn $name(pool: &DbPool, $($arg: $ty),*) -> $ret {
+                match pool {
+                    #[cfg(feature = "sqlite")]
+                    DbPool::Sqlite(pool) => sqlite::$name(pool, $($arg),*).await,
+                    #[cfg(feature = "postgres")]
+                    DbPool::Postgres(pool) => postgres::$name(pool, $($arg),*).await,
+                }
+            }
+        )*
+    };
 }
 
-pub async fn delete_user_session(
-    pool: &SqlitePool,
-    session_id: &str,
-) -> Result<bool, anyhow::Error> {
-    let session_id = Uuid::from_str(session_id)?;
-    let mut conn = pool.acquire().await?;
+db_functions! {
+    pub async fn get_user_by_account_id(
+        provider: AuthProvider,
+        account_id: String,
+    ) -> Result<Option<User>, anyhow::Error>;
+
+    /// Returns an user that is not disabled.
+    pub async fn get_enabled_user(user_id: Uuid) -> Result<Option<User>, anyhow::Error>;
+
+    /// Updates when the session was last used and when it expires.
+    pub async fn touch_user_session(
+        token_hash: &str,
+        last_seen_at: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), anyhow::Error>;
+
+    pub async fn create_user(
+        account_id: String,
+        provider: AuthProvider,
+        username: String,
+        image_url: Option<String>,
+    ) -> Result<User, anyhow::Error>;
+
+    /// Creates a session identified by the hash of its token, the token itself is only known by the client.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_user_session(
+        token_hash: &str,
+        user_id: Uuid,
+        session_duration: Duration,
+        second_factor_pending: bool,
+        persistent: bool,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<UserSession, anyhow::Error>;
+
+    pub async fn get_user_session(token_hash: &str) -> Result<Option<UserSession>, anyhow::Error>;
+
+    pub async fn delete_user_session(token_hash: &str) -> Result<bool, anyhow::Error>;
+
+    /// Marks the second factor of a pending session as verified, the session becomes a regular session.
+    pub async fn complete_session_second_factor(
+        token_hash: &str,
+        session_duration: Duration,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Increments the failed second factor attempts of a pending session and returns the current count.
+    pub async fn record_failed_second_factor(token_hash: &str) -> Result<i64, anyhow::Error>;
+
+    /// Deletes up to `limit` expired sessions, returns how many were deleted.
+    pub async fn delete_expired_user_sessions(limit: i64) -> Result<u64, anyhow::Error>;
+
+    /// Returns which of the given users have sessions that are not expired.
+    pub async fn get_users_with_sessions(user_ids: &[Uuid]) -> Result<HashSet<Uuid>, anyhow::Error>;
+
+    /// Deletes up to `limit` email sign-in links that expired before being used.
+    pub async fn delete_expired_email_login_tokens(limit: i64) -> Result<u64, anyhow::Error>;
+
+    /// Deletes up to `limit` passkey challenges of ceremonies that were never finished.
+    pub async fn delete_expired_passkey_challenges(limit: i64) -> Result<u64, anyhow::Error>;
+
+    pub async fn create_email_login_token(
+        email: &str,
+        token_hash: &str,
+        token_duration: Duration,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Deletes the login token and returns the email it was issued for, if the token exists and is not expired.
+    pub async fn consume_email_login_token(
+        token_hash: &str,
+    ) -> Result<Option<String>, anyhow::Error>;
+
+    pub async fn create_user_passkey(
+        user_id: Uuid,
+        name: String,
+        passkey: &Passkey,
+    ) -> Result<UserPasskey, anyhow::Error>;
+
+    pub async fn get_user_passkeys(user_id: Uuid) -> Result<Vec<UserPasskey>, anyhow::Error>;
+
+    pub async fn get_user_passkey_credentials(user_id: Uuid) -> Result<Vec<Passkey>, anyhow::Error>;
+
+    /// Returns the owner and the credential of the passkey with the given hex encoded credential id.
+    pub async fn get_passkey_by_credential_id(
+        credential_id: &str,
+    ) -> Result<Option<(Uuid, Passkey)>, anyhow::Error>;
+
+    pub async fn update_passkey_credential(passkey: &Passkey) -> Result<(), anyhow::Error>;
+
+    pub async fn delete_user_passkey(
+        user_id: Uuid,
+        passkey_id: &str,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Stores the state of a passkey ceremony, the returned id is sent to the client to finish the ceremony.
+    pub async fn create_passkey_challenge(
+        user_id: Option<Uuid>,
+        state: String,
+        challenge_duration: Duration,
+    ) -> Result<Uuid, anyhow::Error>;
 
-    let result = sqlx::query!("DELETE FROM user_session WHERE id = ?1", session_id)
-        .execute(&mut *conn)
-        .await?;
+    /// Deletes the passkey challenge and returns its state if it was not expired,
+    /// a challenge started by an user can only be consumed by the same user.
+    pub async fn consume_passkey_challenge(
+        challenge_id: &str,
+        user_id: Option<Uuid>,
+    ) -> Result<Option<String>, anyhow::Error>;
 
-    Ok(result.rows_affected() > 0)
+    pub async fn get_user_totp(user_id: Uuid) -> Result<Option<UserTotp>, anyhow::Error>;
+
+    /// Returns `true` if the user has a confirmed second factor.
+    pub async fn has_second_factor(user_id: Uuid) -> Result<bool, anyhow::Error>;
+
+    /// Stores a new secret for the user, this replaces any previous unconfirmed secret
+    /// but never a confirmed one.
+    pub async fn create_unconfirmed_user_totp(
+        user_id: Uuid,
+        secret: &str,
+    ) -> Result<bool, anyhow::Error>;
+
+    pub async fn confirm_user_totp(user_id: Uuid, used_step: i64) -> Result<bool, anyhow::Error>;
+
+    /// Records the time step of the last accepted code, returns `false` if a code
+    /// for the same or a later step was already used.
+    pub async fn update_totp_last_used_step(
+        user_id: Uuid,
+        used_step: i64,
+    ) -> Result<bool, anyhow::Error>;
+
+    pub async fn delete_user_totp(user_id: Uuid) -> Result<bool, anyhow::Error>;
+
+    /// Replaces all the recovery codes of the user with the given code hashes.
+    pub async fn replace_recovery_codes(
+        user_id: Uuid,
+        code_hashes: &[String],
+    ) -> Result<(), anyhow::Error>;
+
+    /// Marks an unused recovery code of the user as used, returns `false` if there was no such code.
+    pub async fn use_recovery_code(user_id: Uuid, code_hash: &str) -> Result<bool, anyhow::Error>;
+
+    /// Marks an unused recovery code as used and returns the user it belongs to,
+    /// this is used to sign in when the user lost access to the provider.
+    pub async fn use_recovery_code_for_login(
+        code_hash: &str,
+    ) -> Result<Option<Uuid>, anyhow::Error>;
+
+    pub async fn count_unused_recovery_codes(user_id: Uuid) -> Result<i64, anyhow::Error>;
+
+    pub async fn delete_recovery_codes(user_id: Uuid) -> Result<(), anyhow::Error>;
+
+    /// Creates an user with the local provider and its password credential.
+    pub async fn create_local_user(
+        username: String,
+        password_hash: String,
+    ) -> Result<User, anyhow::Error>;
+
+    /// Turns a guest into an user with the local provider and its password credential, keeping all its data.
+    /// The guest sessions must be deleted from the session store, like for `upgrade_guest_user`.
+    pub async fn upgrade_guest_to_local_user(
+        guest_id: Uuid,
+        username: String,
+        password_hash: String,
+    ) -> Result<User, anyhow::Error>;
+
+    pub async fn get_user_credential_by_username(
+        username: &str,
+    ) -> Result<Option<UserCredential>, anyhow::Error>;
+
+    pub async fn get_user_credential(
+        user_id: Uuid,
+    ) -> Result<Option<UserCredential>, anyhow::Error>;
+
+    /// Increments the failed login attempts, the credential is locked when `max_attempts` is reached.
+    pub async fn record_failed_login(
+        user_id: Uuid,
+        max_attempts: i64,
+        lockout_duration: Duration,
+    ) -> Result<(), anyhow::Error>;
+
+    pub async fn reset_failed_logins(user_id: Uuid) -> Result<(), anyhow::Error>;
+
+    pub async fn update_user_password(
+        user_id: Uuid,
+        password_hash: String,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Deletes all the sessions of the user except the given one.
+    pub async fn delete_other_user_sessions(
+        user_id: Uuid,
+        token_hash: &str,
+    ) -> Result<u64, anyhow::Error>;
+
+    /// Deletes the user and all the data that belongs to it, its sessions must be deleted from the session store first.
+    pub async fn delete_user(user_id: Uuid) -> Result<bool, anyhow::Error>;
+
+    /// Turns a guest into an user of the given provider, keeping all its data.
+    /// The guest sessions must be deleted from the session store, a new session should be created for the upgraded user.
+    pub async fn upgrade_guest_user(
+        guest_id: Uuid,
+        account_id: String,
+        provider: AuthProvider,
+        username: String,
+        image_url: Option<String>,
+    ) -> Result<User, anyhow::Error>;
+
+    /// Deletes a guest that signed in to an existing user, along with its passkeys and settings.
+    /// Its sessions must be deleted from the session store first.
+    pub async fn merge_guest_user(guest_id: Uuid, user_id: Uuid) -> Result<(), anyhow::Error>;
+
+    /// Returns a page of the guests created before `created_before`, the newer ones may not have a session yet.
+    /// The guests are ordered by id, the next page starts after the last id of the previous one.
+    pub async fn get_guest_user_ids(
+        created_before: NaiveDateTime,
+        after_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Uuid>, anyhow::Error>;
+
+    pub async fn get_roles() -> Result<Vec<Role>, anyhow::Error>;
+
+    pub async fn get_user_roles(user_id: Uuid) -> Result<Vec<Role>, anyhow::Error>;
+
+    /// Gives a role to an user, returns `false` if the user or the role don't exist.
+    pub async fn grant_user_role(user_id: Uuid, role_name: &str) -> Result<bool, anyhow::Error>;
+
+    pub async fn revoke_user_role(user_id: Uuid, role_name: &str) -> Result<bool, anyhow::Error>;
+
+    /// Updates the last login of an user, returns `false` if the user is disabled and can't sign in.
+    pub async fn record_user_login(user_id: Uuid) -> Result<bool, anyhow::Error>;
+
+    /// Returns a page of the users which username or account id contains the search term,
+    /// ignoring the case, along with the total of users found.
+    pub async fn search_users(
+        search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<UserSummary>, i64), anyhow::Error>;
+
+    pub async fn get_user_summary(user_id: Uuid) -> Result<Option<UserSummary>, anyhow::Error>;
+
+    /// Returns the sessions of an user that are not expired, `current_token_hash` is the session making the request.
+    pub async fn get_user_sessions_info(
+        user_id: Uuid,
+        current_token_hash: Option<&str>,
+    ) -> Result<Vec<UserSessionInfo>, anyhow::Error>;
+
+    pub async fn delete_user_sessions(user_id: Uuid) -> Result<u64, anyhow::Error>;
+
+    pub async fn delete_user_session_by_handle(
+        user_id: Uuid,
+        handle: i64,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Disables or enables an user, the sessions of a disabled user are rejected but must be deleted from the session store.
+    pub async fn set_user_disabled(user_id: Uuid, disabled: bool) -> Result<bool, anyhow::Error>;
 }
 
-pub async fn delete_expired_user_sessions(
-    pool: &SqlitePool,
-    user_id: Uuid,
-) -> Result<usize, anyhow::Error> {
-    let now = chrono::offset::Utc::now().naive_utc();
-    let result = sqlx::query!(
-        r#"
-            DELETE FROM user_session 
-            WHERE user_id = ?1 AND ?2 > expires_at
-        "#,
-        user_id,
-        now
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(result.rows_affected() as usize)
+fn role_from_row(id: i64, name: String, permissions: String) -> Role {
+    let permissions = permissions
+        .split_whitespace()
+        .filter_map(|x| match Permission::from_str(x) {
+            Ok(permission) => Some(permission),
+            Err(err) => {
+                tracing::warn!("role '{name}' has an invalid permission: {err}");
+                None
+            }
+        })
+        .collect();
+
+    Role {
+        id,
+        name,
+        permissions,
+    }
 }
@@ -0,0 +1,1241 @@
+use std::{collections::HashSet, str::FromStr, time::Duration};
+
+use chrono::NaiveDateTime;
+
+use super::role_from_row;
+use crate::models::{
+    AuthProvider, Role, User, UserCredential, UserPasskey, UserSession, UserSessionInfo,
+    UserSummary, UserTotp,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
+
+// The queries are checked when they run, the macros can only check the queries against one database
+
+pub async fn get_user_by_account_id(
+    pool: &PgPool,
+    provider: AuthProvider,
+    account_id: String,
+) -> Result<Option<User>, anyhow::Error> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+            SELECT id, account_id, provider, username, image_url
+            FROM "user"
+            WHERE account_id = $1 AND provider = $2
+        "#,
+    )
+    .bind(account_id)
+    .bind(provider.to_string())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn get_enabled_user(pool: &PgPool, user_id: Uuid) -> Result<Option<User>, anyhow::Error> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+            SELECT id, account_id, provider, username, image_url
+            FROM "user"
+            WHERE id = $1 AND disabled = FALSE
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn touch_user_session(
+    pool: &PgPool,
+    token_hash: &str,
+    last_seen_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+) -> Result<(), anyhow::Error> {
+    sqlx::query("UPDATE user_session SET last_seen_at = $2, expires_at = $3 WHERE token_hash = $1")
+        .bind(token_hash)
+        .bind(last_seen_at)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn create_user(
+    pool: &PgPool,
+    account_id: String,
+    provider: AuthProvider,
+    username: String,
+    image_url: Option<String>,
+) -> Result<User, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let new_user = sqlx::query_as::<_, User>(
+        r#"
+            INSERT INTO "user" (id, account_id, provider, username, image_url, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, account_id, provider, username, image_url
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(account_id)
+    .bind(provider.to_string())
+    .bind(username)
+    .bind(image_url)
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(new_user)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_user_session(
+    pool: &PgPool,
+    token_hash: &str,
+    user_id: Uuid,
+    session_duration: Duration,
+    second_factor_pending: bool,
+    persistent: bool,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<UserSession, anyhow::Error> {
+    let created_at = chrono::offset::Utc::now().naive_utc();
+    let expires_at = created_at + session_duration;
+
+    let user_session = sqlx::query_as::<_, UserSession>(
+        r#"
+            INSERT INTO user_session (
+                token_hash,
+                user_id,
+                created_at,
+                expires_at,
+                second_factor_pending,
+                ip_address,
+                user_agent,
+                last_seen_at,
+                persistent
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $3, $8)
+            RETURNING
+                token_hash,
+                user_id,
+                created_at,
+                expires_at,
+                last_seen_at,
+                second_factor_pending,
+                persistent
+        "#,
+    )
+    .bind(token_hash)
+    .bind(user_id)
+    .bind(created_at)
+    .bind(expires_at)
+    .bind(second_factor_pending)
+    .bind(ip_address)
+    .bind(user_agent)
+    .bind(persistent)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user_session)
+}
+
+pub async fn get_user_session(
+    pool: &PgPool,
+    token_hash: &str,
+) -> Result<Option<UserSession>, anyhow::Error> {
+    let user_session = sqlx::query_as::<_, UserSession>(
+        r#"
+            SELECT
+                token_hash,
+                user_id,
+                created_at,
+                expires_at,
+                last_seen_at,
+                second_factor_pending,
+                persistent
+            FROM user_session
+            WHERE token_hash = $1
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_session)
+}
+
+pub async fn delete_user_session(pool: &PgPool, token_hash: &str) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM user_session WHERE token_hash = $1")
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn complete_session_second_factor(
+    pool: &PgPool,
+    token_hash: &str,
+    session_duration: Duration,
+) -> Result<bool, anyhow::Error> {
+    let expires_at = chrono::offset::Utc::now().naive_utc() + session_duration;
+    let result = sqlx::query(
+        r#"
+            UPDATE user_session
+            SET second_factor_pending = FALSE, expires_at = $2
+            WHERE token_hash = $1 AND second_factor_pending = TRUE
+        "#,
+    )
+    .bind(token_hash)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn record_failed_second_factor(
+    pool: &PgPool,
+    token_hash: &str,
+) -> Result<i64, anyhow::Error> {
+    let attempts = sqlx::query_scalar::<_, i64>(
+        r#"
+            UPDATE user_session
+            SET second_factor_attempts = second_factor_attempts + 1
+            WHERE token_hash = $1
+            RETURNING second_factor_attempts
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(attempts.unwrap_or_default())
+}
+
+pub async fn delete_expired_user_sessions(pool: &PgPool, limit: i64) -> Result<u64, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let result = sqlx::query(
+        r#"
+            DELETE FROM user_session
+            WHERE token_hash IN (
+                SELECT token_hash FROM user_session WHERE $1 > expires_at LIMIT $2
+            )
+        "#,
+    )
+    .bind(now)
+    .bind(limit)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn get_users_with_sessions(
+    pool: &PgPool,
+    user_ids: &[Uuid],
+) -> Result<HashSet<Uuid>, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let users = sqlx::query_scalar::<_, Uuid>(
+        "SELECT DISTINCT user_id FROM user_session WHERE expires_at > $1 AND user_id = ANY($2)",
+    )
+    .bind(now)
+    .bind(user_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users.into_iter().collect())
+}
+
+pub async fn delete_expired_email_login_tokens(
+    pool: &PgPool,
+    limit: i64,
+) -> Result<u64, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let result = sqlx::query(
+        r#"
+            DELETE FROM email_login_token
+            WHERE token_hash IN (
+                SELECT token_hash FROM email_login_token WHERE $1 > expires_at LIMIT $2
+            )
+        "#,
+    )
+    .bind(now)
+    .bind(limit)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_expired_passkey_challenges(
+    pool: &PgPool,
+    limit: i64,
+) -> Result<u64, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let result = sqlx::query(
+        r#"
+            DELETE FROM passkey_challenge
+            WHERE id IN (
+                SELECT id FROM passkey_challenge WHERE $1 > expires_at LIMIT $2
+            )
+        "#,
+    )
+    .bind(now)
+    .bind(limit)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn create_email_login_token(
+    pool: &PgPool,
+    email: &str,
+    token_hash: &str,
+    token_duration: Duration,
+) -> Result<(), anyhow::Error> {
+    let created_at = chrono::offset::Utc::now().naive_utc();
+    let expires_at = created_at + token_duration;
+
+    sqlx::query(
+        r#"
+            INSERT INTO email_login_token (token_hash, email, created_at, expires_at)
+            VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(token_hash)
+    .bind(email)
+    .bind(created_at)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn consume_email_login_token(
+    pool: &PgPool,
+    token_hash: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let email = sqlx::query_scalar::<_, String>(
+        r#"
+            DELETE FROM email_login_token
+            WHERE token_hash = $1 AND expires_at > $2
+            RETURNING email
+        "#,
+    )
+    .bind(token_hash)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(email)
+}
+
+pub async fn create_user_passkey(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: String,
+    passkey: &Passkey,
+) -> Result<UserPasskey, anyhow::Error> {
+    let id = hex::encode(passkey.cred_id());
+    let serialized = serde_json::to_string(passkey)?;
+    let created_at = chrono::offset::Utc::now().naive_utc();
+
+    let user_passkey = sqlx::query_as::<_, UserPasskey>(
+        r#"
+            INSERT INTO user_passkey (id, user_id, name, passkey, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, name, created_at, last_used_at
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(name)
+    .bind(serialized)
+    .bind(created_at)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user_passkey)
+}
+
+pub async fn get_user_passkeys(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<UserPasskey>, anyhow::Error> {
+    let passkeys = sqlx::query_as::<_, UserPasskey>(
+        r#"
+            SELECT id, user_id, name, created_at, last_used_at
+            FROM user_passkey
+            WHERE user_id = $1
+            ORDER BY created_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(passkeys)
+}
+
+pub async fn get_user_passkey_credentials(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<Passkey>, anyhow::Error> {
+    let rows =
+        sqlx::query_scalar::<_, String>("SELECT passkey FROM user_passkey WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+
+    let passkeys = rows
+        .iter()
+        .map(|x| serde_json::from_str::<Passkey>(x))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(passkeys)
+}
+
+pub async fn get_passkey_by_credential_id(
+    pool: &PgPool,
+    credential_id: &str,
+) -> Result<Option<(Uuid, Passkey)>, anyhow::Error> {
+    let row = sqlx::query_as::<_, (Uuid, String)>(
+        "SELECT user_id, passkey FROM user_passkey WHERE id = $1",
+    )
+    .bind(credential_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some((user_id, passkey)) => Ok(Some((user_id, serde_json::from_str(&passkey)?))),
+        None => Ok(None),
+    }
+}
+
+pub async fn update_passkey_credential(
+    pool: &PgPool,
+    passkey: &Passkey,
+) -> Result<(), anyhow::Error> {
+    let id = hex::encode(passkey.cred_id());
+    let serialized = serde_json::to_string(passkey)?;
+    let now = chrono::offset::Utc::now().naive_utc();
+
+    sqlx::query(
+        r#"
+            UPDATE user_passkey
+            SET passkey = $2, last_used_at = $3
+            WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(serialized)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_user_passkey(
+    pool: &PgPool,
+    user_id: Uuid,
+    passkey_id: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM user_passkey WHERE id = $1 AND user_id = $2")
+        .bind(passkey_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn create_passkey_challenge(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    state: String,
+    challenge_duration: Duration,
+) -> Result<Uuid, anyhow::Error> {
+    let id = Uuid::new_v4();
+    let expires_at = chrono::offset::Utc::now().naive_utc() + challenge_duration;
+
+    sqlx::query(
+        r#"
+            INSERT INTO passkey_challenge (id, user_id, state, expires_at)
+            VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(state)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(id)
+}
+
+pub async fn consume_passkey_challenge(
+    pool: &PgPool,
+    challenge_id: &str,
+    user_id: Option<Uuid>,
+) -> Result<Option<String>, anyhow::Error> {
+    let challenge_id = Uuid::from_str(challenge_id)?;
+    let now = chrono::offset::Utc::now().naive_utc();
+    let state = sqlx::query_scalar::<_, String>(
+        r#"
+            DELETE FROM passkey_challenge
+            WHERE id = $1 AND user_id IS NOT DISTINCT FROM $2 AND expires_at > $3
+            RETURNING state
+        "#,
+    )
+    .bind(challenge_id)
+    .bind(user_id)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(state)
+}
+
+pub async fn get_user_totp(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<UserTotp>, anyhow::Error> {
+    let user_totp = sqlx::query_as::<_, UserTotp>(
+        "SELECT secret, confirmed_at, last_used_step FROM user_totp WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_totp)
+}
+
+pub async fn has_second_factor(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM user_totp WHERE user_id = $1 AND confirmed_at IS NOT NULL
+            )
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(exists)
+}
+
+pub async fn create_unconfirmed_user_totp(
+    pool: &PgPool,
+    user_id: Uuid,
+    secret: &str,
+) -> Result<bool, anyhow::Error> {
+    let created_at = chrono::offset::Utc::now().naive_utc();
+    let result = sqlx::query(
+        r#"
+            INSERT INTO user_totp (user_id, secret, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = excluded.secret, created_at = excluded.created_at, last_used_step = NULL
+            WHERE user_totp.confirmed_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(secret)
+    .bind(created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn confirm_user_totp(
+    pool: &PgPool,
+    user_id: Uuid,
+    used_step: i64,
+) -> Result<bool, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let result = sqlx::query(
+        r#"
+            UPDATE user_totp
+            SET confirmed_at = $2, last_used_step = $3
+            WHERE user_id = $1 AND confirmed_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(now)
+    .bind(used_step)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn update_totp_last_used_step(
+    pool: &PgPool,
+    user_id: Uuid,
+    used_step: i64,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+            UPDATE user_totp
+            SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(used_step)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_user_totp(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn replace_recovery_codes(
+    pool: &PgPool,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> Result<(), anyhow::Error> {
+    let created_at = chrono::offset::Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM user_recovery_code WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code_hash in code_hashes {
+        sqlx::query(
+            r#"
+                INSERT INTO user_recovery_code (id, user_id, code_hash, created_at)
+                VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(code_hash)
+        .bind(created_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn use_recovery_code(
+    pool: &PgPool,
+    user_id: Uuid,
+    code_hash: &str,
+) -> Result<bool, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let result = sqlx::query(
+        r#"
+            UPDATE user_recovery_code
+            SET used_at = $3
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(code_hash)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn use_recovery_code_for_login(
+    pool: &PgPool,
+    code_hash: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let user_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+            UPDATE user_recovery_code
+            SET used_at = $2
+            WHERE code_hash = $1 AND used_at IS NULL
+            RETURNING user_id
+        "#,
+    )
+    .bind(code_hash)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
+pub async fn count_unused_recovery_codes(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<i64, anyhow::Error> {
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM user_recovery_code WHERE user_id = $1 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+pub async fn delete_recovery_codes(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query("DELETE FROM user_recovery_code WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn create_local_user(
+    pool: &PgPool,
+    username: String,
+    password_hash: String,
+) -> Result<User, anyhow::Error> {
+    let id = Uuid::new_v4();
+    let account_id = username.to_lowercase();
+    let now = chrono::offset::Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+
+    let new_user = sqlx::query_as::<_, User>(
+        r#"
+            INSERT INTO "user" (id, account_id, provider, username, image_url, created_at)
+            VALUES ($1, $2, $3, $4, NULL, $5)
+            RETURNING id, account_id, provider, username, image_url
+        "#,
+    )
+    .bind(id)
+    .bind(&account_id)
+    .bind(AuthProvider::Local.to_string())
+    .bind(username)
+    .bind(now)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+            INSERT INTO user_credential (user_id, username, password_hash, updated_at)
+            VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(id)
+    .bind(account_id)
+    .bind(password_hash)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(new_user)
+}
+
+pub async fn upgrade_guest_to_local_user(
+    pool: &PgPool,
+    guest_id: Uuid,
+    username: String,
+    password_hash: String,
+) -> Result<User, anyhow::Error> {
+    let account_id = username.to_lowercase();
+    let now = chrono::offset::Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+
+    let user = sqlx::query_as::<_, User>(
+        r#"
+            UPDATE "user"
+            SET account_id = $2, provider = $3, username = $4, image_url = NULL
+            WHERE id = $1 AND provider = $5
+            RETURNING id, account_id, provider, username, image_url
+        "#,
+    )
+    .bind(guest_id)
+    .bind(&account_id)
+    .bind(AuthProvider::Local.to_string())
+    .bind(username)
+    .bind(AuthProvider::Guest.to_string())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+            INSERT INTO user_credential (user_id, username, password_hash, updated_at)
+            VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(guest_id)
+    .bind(account_id)
+    .bind(password_hash)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(user)
+}
+
+pub async fn get_user_credential_by_username(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<UserCredential>, anyhow::Error> {
+    let credential = sqlx::query_as::<_, UserCredential>(
+        r#"
+            SELECT user_id, password_hash, failed_attempts, locked_until
+            FROM user_credential
+            WHERE username = $1
+        "#,
+    )
+    .bind(username.to_lowercase())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(credential)
+}
+
+pub async fn get_user_credential(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<UserCredential>, anyhow::Error> {
+    let credential = sqlx::query_as::<_, UserCredential>(
+        r#"
+            SELECT user_id, password_hash, failed_attempts, locked_until
+            FROM user_credential
+            WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(credential)
+}
+
+pub async fn record_failed_login(
+    pool: &PgPool,
+    user_id: Uuid,
+    max_attempts: i64,
+    lockout_duration: Duration,
+) -> Result<(), anyhow::Error> {
+    let locked_until = chrono::offset::Utc::now().naive_utc() + lockout_duration;
+    sqlx::query(
+        r#"
+            UPDATE user_credential
+            SET
+                failed_attempts = failed_attempts + 1,
+                locked_until = CASE WHEN failed_attempts + 1 >= $2 THEN $3 ELSE locked_until END
+            WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(max_attempts)
+    .bind(locked_until)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn reset_failed_logins(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query(
+        r#"
+            UPDATE user_credential
+            SET failed_attempts = 0, locked_until = NULL
+            WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_user_password(
+    pool: &PgPool,
+    user_id: Uuid,
+    password_hash: String,
+) -> Result<(), anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    sqlx::query(
+        r#"
+            UPDATE user_credential
+            SET password_hash = $2, updated_at = $3, failed_attempts = 0, locked_until = NULL
+            WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(password_hash)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_other_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_hash: &str,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM user_session WHERE user_id = $1 AND token_hash != $2")
+        .bind(user_id)
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_user(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    for table in [
+        "passkey_challenge",
+        "user_passkey",
+        "user_totp",
+        "user_recovery_code",
+        "user_credential",
+        "user_role",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE user_id = $1"))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let result = sqlx::query(r#"DELETE FROM "user" WHERE id = $1"#)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn upgrade_guest_user(
+    pool: &PgPool,
+    guest_id: Uuid,
+    account_id: String,
+    provider: AuthProvider,
+    username: String,
+    image_url: Option<String>,
+) -> Result<User, anyhow::Error> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+            UPDATE "user"
+            SET account_id = $2, provider = $3, username = $4, image_url = $5
+            WHERE id = $1 AND provider = $6
+            RETURNING id, account_id, provider, username, image_url
+        "#,
+    )
+    .bind(guest_id)
+    .bind(account_id)
+    .bind(provider.to_string())
+    .bind(username)
+    .bind(image_url)
+    .bind(AuthProvider::Guest.to_string())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn merge_guest_user(
+    pool: &PgPool,
+    guest_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let is_guest = sqlx::query_scalar::<_, bool>(
+        r#"SELECT EXISTS(SELECT 1 FROM "user" WHERE id = $1 AND provider = $2)"#,
+    )
+    .bind(guest_id)
+    .bind(AuthProvider::Guest.to_string())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if !is_guest || guest_id == user_id {
+        anyhow::bail!("User '{guest_id}' is not a guest that can be merged into '{user_id}'");
+    }
+
+    // The account settings of the user are kept, a passkey of the guest would sign in to the user
+    for table in [
+        "user_passkey",
+        "passkey_challenge",
+        "user_totp",
+        "user_recovery_code",
+        "user_role",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE user_id = $1"))
+            .bind(guest_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query(r#"DELETE FROM "user" WHERE id = $1"#)
+        .bind(guest_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_guest_user_ids(
+    pool: &PgPool,
+    created_before: NaiveDateTime,
+    after_id: Option<Uuid>,
+    limit: i64,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let guest_ids = sqlx::query_scalar::<_, Uuid>(
+        r#"
+            SELECT id FROM "user"
+            WHERE provider = $1 AND created_at < $2 AND ($3::uuid IS NULL OR id > $3)
+            ORDER BY id
+            LIMIT $4
+        "#,
+    )
+    .bind(AuthProvider::Guest.to_string())
+    .bind(created_before)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(guest_ids)
+}
+
+pub async fn get_roles(pool: &PgPool) -> Result<Vec<Role>, anyhow::Error> {
+    let roles = sqlx::query_as::<_, (i64, String, String)>(
+        "SELECT id, name, permissions FROM role ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(id, name, permissions)| role_from_row(id, name, permissions))
+    .collect();
+
+    Ok(roles)
+}
+
+pub async fn get_user_roles(pool: &PgPool, user_id: Uuid) -> Result<Vec<Role>, anyhow::Error> {
+    let roles = sqlx::query_as::<_, (i64, String, String)>(
+        r#"
+            SELECT role.id, role.name, role.permissions
+            FROM role
+            INNER JOIN user_role ON user_role.role_id = role.id
+            WHERE user_role.user_id = $1
+            ORDER BY role.name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(id, name, permissions)| role_from_row(id, name, permissions))
+    .collect();
+
+    Ok(roles)
+}
+
+pub async fn grant_user_role(
+    pool: &PgPool,
+    user_id: Uuid,
+    role_name: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO user_role (user_id, role_id)
+            SELECT "user".id, role.id FROM "user", role
+            WHERE "user".id = $1 AND role.name = $2
+            ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(role_name)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn revoke_user_role(
+    pool: &PgPool,
+    user_id: Uuid,
+    role_name: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+            DELETE FROM user_role
+            WHERE user_id = $1 AND role_id = (SELECT id FROM role WHERE name = $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(role_name)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn record_user_login(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let result =
+        sqlx::query(r#"UPDATE "user" SET last_login_at = $2 WHERE id = $1 AND disabled = FALSE"#)
+            .bind(user_id)
+            .bind(now)
+            .execute(pool)
+            .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn search_users(
+    pool: &PgPool,
+    search: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<UserSummary>, i64), anyhow::Error> {
+    // Escape the wildcards of the search term
+    let pattern = search.map(|x| {
+        let escaped = x
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{escaped}%")
+    });
+
+    let users = sqlx::query_as::<_, UserSummary>(
+        r#"
+            SELECT id, account_id, provider, username, image_url, created_at, last_login_at, disabled
+            FROM "user"
+            WHERE $1::TEXT IS NULL OR username ILIKE $1 ESCAPE '\' OR account_id ILIKE $1 ESCAPE '\'
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+        "#,
+    )
+    .bind(&pattern)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let total = sqlx::query_scalar::<_, i64>(
+        r#"
+            SELECT COUNT(*)
+            FROM "user"
+            WHERE $1::TEXT IS NULL OR username ILIKE $1 ESCAPE '\' OR account_id ILIKE $1 ESCAPE '\'
+        "#,
+    )
+    .bind(&pattern)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((users, total))
+}
+
+pub async fn get_user_summary(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<UserSummary>, anyhow::Error> {
+    let user = sqlx::query_as::<_, UserSummary>(
+        r#"
+            SELECT id, account_id, provider, username, image_url, created_at, last_login_at, disabled
+            FROM "user"
+            WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn get_user_sessions_info(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_token_hash: Option<&str>,
+) -> Result<Vec<UserSessionInfo>, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let sessions = sqlx::query_as::<_, UserSessionInfo>(
+        r#"
+            SELECT
+                handle,
+                created_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent,
+                second_factor_pending,
+                COALESCE(token_hash = $3, FALSE) as current
+            FROM user_session
+            WHERE user_id = $1 AND expires_at > $2
+            ORDER BY COALESCE(last_seen_at, created_at) DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(now)
+    .bind(current_token_hash)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+pub async fn delete_user_sessions(pool: &PgPool, user_id: Uuid) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM user_session WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_user_session_by_handle(
+    pool: &PgPool,
+    user_id: Uuid,
+    handle: i64,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM user_session WHERE handle = $1 AND user_id = $2")
+        .bind(handle)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_user_disabled(
+    pool: &PgPool,
+    user_id: Uuid,
+    disabled: bool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(r#"UPDATE "user" SET disabled = $2 WHERE id = $1"#)
+        .bind(user_id)
+        .bind(disabled)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,24 @@
+//! Sign in with oauth providers, email links, passkeys, passwords or as a guest, with sessions,
+//! second factors, roles and the pages to manage them, see [`AuthRouter`].
+
+pub mod cli;
+pub mod config;
+mod constants;
+pub mod db;
+pub mod mailer;
+mod misc;
+pub mod models;
+pub mod providers;
+mod routes;
+pub mod server;
+pub mod session_store;
+pub mod tasks;
+
+pub use misc::{http::create_http_client, Theme};
+pub use routes::{
+    error_handler_middleware, not_found, AuthRouter, AuthRouterBuilder, LocalAuthConfig,
+};
+pub use server::{
+    session_cookie_middleware, AppState, CurrentUser, PendingSecondFactorUser, RequireAuth,
+    RequireRole, UserTheme,
+};
@@ -0,0 +1,214 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use axum::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::config::{ConfigReader, Secret};
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), anyhow::Error>;
+}
+
+/// Sends emails through a SMTP server.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+            .with_context(|| format!("Invalid SMTP host: {host}"))?;
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        let from = from
+            .parse::<Mailbox>()
+            .with_context(|| format!("Invalid sender address: {from}"))?;
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), anyhow::Error> {
+        let to = email
+            .to
+            .parse::<Mailbox>()
+            .with_context(|| format!("Invalid recipient address: {}", email.to))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)
+            .context("Failed to build email")?;
+
+        self.transport
+            .send(message)
+            .await
+            .context("Failed to send email")?;
+
+        Ok(())
+    }
+}
+
+/// Writes each email to a file in `outbox_dir` instead of sending it.
+///
+/// Useful for development and tests where there is no mail server available. Only the recipient
+/// and the subject are logged, the body has the sign-in links.
+#[derive(Debug)]
+pub struct LogMailer {
+    outbox_dir: PathBuf,
+}
+
+impl LogMailer {
+    pub fn new(outbox_dir: PathBuf) -> Self {
+        LogMailer { outbox_dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), anyhow::Error> {
+        tokio::fs::create_dir_all(&self.outbox_dir)
+            .await
+            .context("Failed to create outbox directory")?;
+
+        // The address is not used in the name, it may have characters that are not valid in a path
+        let timestamp = chrono::offset::Utc::now().format("%Y%m%d%H%M%S%f");
+        let id = uuid::Uuid::new_v4().simple();
+        let path = self.outbox_dir.join(format!("{timestamp}-{id}.txt"));
+        let contents = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            email.to, email.subject, email.body
+        );
+
+        tokio::fs::write(&path, contents)
+            .await
+            .with_context(|| format!("Failed to write email to {path:?}"))?;
+
+        tracing::info!(
+            "email to '{}' with subject '{}' was written to {path:?}",
+            email.to,
+            email.subject
+        );
+
+        Ok(())
+    }
+}
+
+/// How the emails are sent, set with `MAILER`, which is `smtp` or `log` for development.
+/// The email sign-in is disabled when it is not set.
+#[derive(Debug, Clone)]
+pub enum MailerConfig {
+    Smtp {
+        host: String,
+        port: Option<u16>,
+        credentials: Option<(String, Secret)>,
+        from: String,
+    },
+    Log {
+        outbox_dir: PathBuf,
+    },
+}
+
+impl MailerConfig {
+    pub fn from_config(reader: &mut ConfigReader) -> Option<Self> {
+        let kind = reader.get("MAILER")?;
+
+        let config = match kind.as_str() {
+            "smtp" => {
+                let host = reader.required("SMTP_HOST").unwrap_or_default();
+                let port =
+                    reader.parse_with("SMTP_PORT", "a port number", |x| x.parse::<u16>().ok());
+                let credentials =
+                    match (reader.get("SMTP_USERNAME"), reader.secret("SMTP_PASSWORD")) {
+                        (Some(username), Some(password)) => Some((username, password)),
+                        _ => None,
+                    };
+
+                let from = reader.required("MAIL_FROM").unwrap_or_default();
+                if !from.is_empty() && from.parse::<Mailbox>().is_err() {
+                    reader.error(format!(
+                        "Invalid 'MAIL_FROM', expected an email address: {from}"
+                    ));
+                }
+
+                MailerConfig::Smtp {
+                    host,
+                    port,
+                    credentials,
+                    from,
+                }
+            }
+            "log" => MailerConfig::Log {
+                outbox_dir: reader
+                    .required("MAILER_OUTBOX_DIR")
+                    .map(PathBuf::from)
+                    .unwrap_or_default(),
+            },
+            _ => {
+                reader.error(format!(
+                    "Invalid 'MAILER', expected 'smtp' or 'log': {kind}"
+                ));
+                return None;
+            }
+        };
+
+        Some(config)
+    }
+}
+
+/// Creates the mailer selected in the config.
+pub fn create_mailer(config: &MailerConfig) -> Result<SharedMailer, anyhow::Error> {
+    match config {
+        MailerConfig::Smtp {
+            host,
+            port,
+            credentials,
+            from,
+        } => {
+            let credentials = credentials
+                .as_ref()
+                .map(|(username, password)| (username.clone(), password.expose().to_owned()));
+            let mailer = SmtpMailer::new(host, *port, credentials, from)?;
+            Ok(Arc::new(mailer))
+        }
+        MailerConfig::Log { outbox_dir } => {
+            tracing::warn!("the emails are written to {outbox_dir:?} instead of being sent");
+            Ok(Arc::new(LogMailer::new(outbox_dir.clone())))
+        }
+    }
+}
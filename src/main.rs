@@ -1,59 +1,220 @@
-mod constants;
-mod db;
-mod misc;
-mod models;
-mod routes;
-mod server;
-
 use anyhow::Context;
-use axum::{middleware, Extension, Router};
+use axum::{routing::get, Router};
+use axum_oauth::{
+    cli::{Cli, Command},
+    config::Config,
+    db::DbPool,
+    AuthRouter,
+};
+use clap::Parser;
 use dotenvy::dotenv;
-use sqlx::sqlite::SqlitePool;
-use std::error::Error;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use std::{error::Error, future::IntoFuture, net::SocketAddr, time::Duration};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing::Level;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            tracing_subscriber::fmt()
+                .with_max_level(Level::DEBUG)
+                .init();
 
-    tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
-        .init();
+            // Settings, all the problems are reported at once
+            serve(Config::load()?).await
+        }
+        Command::Admin(command) => {
+            // Only the problems are logged, the output of the command is in stdout
+            tracing_subscriber::fmt()
+                .with_max_level(Level::WARN)
+                .with_writer(std::io::stderr)
+                .init();
 
+            axum_oauth::cli::run(command, Config::load()?).await?;
+            Ok(())
+        }
+    }
+}
+
+async fn serve(config: Config) -> Result<(), Box<dyn Error>> {
     // Database
-    let connection_string = std::env::var("DATABASE_URL").context("'DATABASE_URL' no found")?;
-    let pool = SqlitePool::connect(&connection_string)
+    let pool = DbPool::connect(config.database_url.expose(), config.auto_migrate)
         .await
         .context("Failed to connect to database")?;
 
+    if config.auto_migrate {
+        pool.run_migrations()
+            .await
+            .context("Failed to run database migrations")?;
+    }
+
+    // Where the sessions are kept
+    let session_store =
+        axum_oauth::session_store::create_session_store(&config.session_store, pool.clone())
+            .await
+            .context("Failed to create session store")?;
+
+    // Metrics, recorded by the background tasks and the session cache
+    if let Some(metrics_address) = config.metrics_address {
+        serve_metrics(metrics_address).await?;
+    }
+
+    // Background tasks, they are stopped with the server
+    let (shutdown_sender, shutdown_receiver) = tokio::sync::watch::channel(false);
+    let background_tasks = axum_oauth::tasks::spawn_background_tasks(
+        pool.clone(),
+        session_store.clone(),
+        config.sweeper,
+        shutdown_receiver,
+    );
+
+    // Providers with credentials, among the ones enabled in the build
+    let http_client = axum_oauth::create_http_client().context("Failed to create http client")?;
+    #[allow(unused_mut)]
+    let mut auth_router = AuthRouter::builder().http_client(http_client.clone());
+
+    #[cfg(feature = "google")]
+    if let Some(credentials) = &config.google {
+        let provider = axum_oauth::providers::GoogleProvider::new(credentials.clone());
+        auth_router = auth_router.provider(provider);
+    }
+
+    #[cfg(feature = "github")]
+    if let Some(credentials) = &config.github {
+        let provider = axum_oauth::providers::GithubProvider::new(credentials.clone());
+        auth_router = auth_router.provider(provider);
+    }
+
+    #[cfg(feature = "discord")]
+    if let Some(credentials) = &config.discord {
+        let provider = axum_oauth::providers::DiscordProvider::new(credentials.clone());
+        auth_router = auth_router.provider(provider);
+    }
+
+    #[cfg(feature = "oidc")]
+    if let Some(oidc) = &config.oidc {
+        let provider = axum_oauth::providers::OidcProvider::discover(&http_client, oidc.clone())
+            .await
+            .context("Failed to discover the OpenID Connect provider")?;
+        auth_router = auth_router.provider(provider);
+    }
+
+    #[cfg(feature = "mock")]
+    if config.mock_auth {
+        tracing::warn!("the mock provider is enabled, anyone can sign in with any username");
+        let provider = axum_oauth::providers::MockProvider::new(&config.base_url);
+        auth_router = auth_router.provider(provider);
+    }
+
+    let host = config.host.clone();
+    let port = config.port;
+
+    let auth_router = auth_router
+        .config(config.auth_config())
+        .pool(pool)
+        .store(session_store)
+        .build()?;
+
     // Routes
     let app = Router::new()
+        .merge(auth_router)
         .merge(public_dir())
-        .merge(crate::routes::api_router())
-        .merge(crate::routes::pages_router())
-        .layer(Extension(pool))
-        .layer(TraceLayer::new_for_http())
-        .layer(middleware::from_fn(crate::routes::error_handler_middleware));
+        .fallback(axum_oauth::not_found)
+        .layer(TraceLayer::new_for_http());
 
     // Start server
-    let host = std::env::var("HOST").context("'HOST' no found")?;
-    let port = std::env::var("PORT")
-        .context("'PORT' no found")
-        .and_then(|x| {
-            x.parse::<u16>()
-                .map_err(|_| anyhow::anyhow!("Invalid port: {x}"))
-        })?;
-
     let listener = tokio::net::TcpListener::bind((host.as_str(), port))
         .await
         .context("Failed to start tcp listener")?;
 
     println!("Listening on: http://{host}:{port}");
-    axum::serve(listener, app)
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    );
+
+    // This version of axum can't drain the open connections, they are dropped when the server stops
+    tokio::select! {
+        result = server.into_future() => result.context("Failed to start server")?,
+        _ = shutdown_signal() => {},
+    }
+
+    // Let the background tasks finish what they are doing
+    shutdown_sender.send(true).ok();
+    background_tasks
         .await
-        .context("Failed to start server")?;
+        .context("Failed to stop background tasks")?;
+
+    Ok(())
+}
+
+/// Completes when the server is asked to stop with `Ctrl+C` or `SIGTERM`.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for ctrl+c: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("shutting down");
+}
+
+/// Installs the Prometheus recorder and serves its metrics on `/metrics` at `address`.
+async fn serve_metrics(address: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let handle = PrometheusBuilder::new()
+        .install_recorder()
+        .context("Failed to install metrics recorder")?;
+
+    // The histograms are only drained when asked to
+    let upkeep_handle = handle.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            upkeep_handle.run_upkeep();
+        }
+    });
+
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .context("Failed to start metrics tcp listener")?;
+
+    let app = Router::new().route("/metrics", get(move || std::future::ready(handle.render())));
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            tracing::error!("failed to serve metrics: {err}");
+        }
+    });
 
+    println!("Metrics on: http://{address}/metrics");
     Ok(())
 }
 
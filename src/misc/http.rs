@@ -0,0 +1,50 @@
+use oauth2::{HttpRequest, HttpResponse};
+
+use crate::constants::{HTTP_CONNECT_TIMEOUT, HTTP_REQUEST_TIMEOUT, HTTP_USER_AGENT};
+
+/// Creates the client shared by the requests to the providers, it keeps the connections open between requests.
+///
+/// Redirects are not followed, the oauth2 docs warn that following them opens the token exchange to SSRF.
+pub fn create_http_client() -> Result<reqwest::Client, anyhow::Error> {
+    let client = reqwest::Client::builder()
+        .user_agent(HTTP_USER_AGENT)
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .timeout(HTTP_REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    Ok(client)
+}
+
+/// Sends the requests of the oauth2 crate with the shared client, like `oauth2::reqwest::async_http_client`
+/// but without creating a new client each time.
+pub async fn oauth_http_client(
+    client: &reqwest::Client,
+    request: HttpRequest,
+) -> Result<HttpResponse, oauth2::reqwest::Error<reqwest::Error>> {
+    let mut request_builder = client
+        .request(request.method, request.url.as_str())
+        .body(request.body);
+
+    for (name, value) in &request.headers {
+        request_builder = request_builder.header(name.as_str(), value.as_bytes());
+    }
+
+    let response = request_builder
+        .send()
+        .await
+        .map_err(oauth2::reqwest::Error::Reqwest)?;
+
+    let status_code = response.status();
+    let headers = response.headers().to_owned();
+    let body = response
+        .bytes()
+        .await
+        .map_err(oauth2::reqwest::Error::Reqwest)?;
+
+    Ok(HttpResponse {
+        status_code,
+        headers,
+        body: body.to_vec(),
+    })
+}
@@ -1,4 +1,10 @@
 pub mod error;
+pub mod http;
+pub mod password;
+pub mod token;
+pub mod totp;
+pub mod user_agent;
+
 use axum::http::StatusCode;
 use std::fmt::Display;
 
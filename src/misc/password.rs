@@ -0,0 +1,131 @@
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Hashes the password with Argon2id, this is slow so it runs in a blocking thread.
+pub async fn hash_password(password: String) -> Result<String, anyhow::Error> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|x| x.to_string())
+            .map_err(|err| anyhow::anyhow!("Failed to hash password: {err}"))
+    })
+    .await
+    .context("Failed to join password hashing task")?
+}
+
+/// Checks the password against an Argon2 hash, this is slow so it runs in a blocking thread.
+///
+/// When there is no hash to check against a dummy one is used, so the response time
+/// don't reveal whether the account exists.
+pub async fn verify_password(
+    password: String,
+    password_hash: Option<String>,
+) -> Result<bool, anyhow::Error> {
+    tokio::task::spawn_blocking(move || {
+        let exists = password_hash.is_some();
+        let password_hash = password_hash.unwrap_or_else(|| dummy_hash().to_owned());
+        let parsed = PasswordHash::new(&password_hash)
+            .map_err(|err| anyhow::anyhow!("Invalid password hash: {err}"))?;
+
+        let valid = Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok();
+
+        Ok(exists && valid)
+    })
+    .await
+    .context("Failed to join password verification task")?
+}
+
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(b"dummy password", &salt)
+            .expect("Failed to hash dummy password")
+            .to_string()
+    })
+}
+
+/// Returns the reason the password is not acceptable, if any.
+pub fn validate_password(password: &str) -> Option<&'static str> {
+    let len = password.chars().count();
+
+    if len < MIN_PASSWORD_LENGTH {
+        return Some("password_too_short");
+    }
+
+    if len > MAX_PASSWORD_LENGTH {
+        return Some("password_too_long");
+    }
+
+    None
+}
+
+/// Returns the reason the username is not acceptable, if any.
+pub fn validate_username(username: &str) -> Option<&'static str> {
+    let valid_chars = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if !(3..=32).contains(&username.len()) || !valid_chars {
+        return Some("invalid_username");
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verifies_hashed_passwords() {
+        let hash = hash_password("correct horse".to_owned()).await.unwrap();
+
+        assert!(
+            verify_password("correct horse".to_owned(), Some(hash.clone()))
+                .await
+                .unwrap()
+        );
+        assert!(!verify_password("wrong horse".to_owned(), Some(hash))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_and_invalid_hashes() {
+        assert!(!verify_password("dummy password".to_owned(), None)
+            .await
+            .unwrap());
+        assert!(
+            verify_password("password".to_owned(), Some("invalid".to_owned()))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn validates_passwords_and_usernames() {
+        assert_eq!(validate_password("short"), Some("password_too_short"));
+        assert_eq!(
+            validate_password(&"a".repeat(129)),
+            Some("password_too_long")
+        );
+        assert_eq!(validate_password("long enough"), None);
+
+        assert_eq!(validate_username("user.name-1"), None);
+        assert_eq!(validate_username("ab"), Some("invalid_username"));
+        assert_eq!(validate_username("user name"), Some("invalid_username"));
+    }
+}
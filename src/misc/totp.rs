@@ -0,0 +1,111 @@
+use anyhow::Context;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::token::constant_time_eq;
+
+const TOTP_ISSUER: &str = "Axum OAuth";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+
+/// Generates a new base32 encoded TOTP secret.
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// Creates the TOTP for the given base32 encoded secret, the account name is displayed in the authenticator app.
+pub fn build_totp(secret: &str, account_name: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|err| anyhow::anyhow!("Invalid TOTP secret: {err:?}"))?;
+
+    // The otpauth uri don't allow ':' in the label
+    let account_name = account_name.replace(':', "");
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP,
+        secret,
+        Some(TOTP_ISSUER.to_owned()),
+        account_name,
+    )
+    .context("Failed to create TOTP")
+}
+
+/// Checks the code against the current time step and its neighbours,
+/// returns the matched time step if it was not used before.
+pub fn verify_code(totp: &TOTP, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let now = chrono::offset::Utc::now().timestamp() as u64;
+    verify_code_at(totp, code, last_used_step, now)
+}
+
+fn verify_code_at(totp: &TOTP, code: &str, last_used_step: Option<i64>, now: u64) -> Option<i64> {
+    let code = code.trim().replace(' ', "");
+    let current_step = now / TOTP_STEP;
+
+    [current_step - 1, current_step, current_step + 1]
+        .into_iter()
+        .filter(|step| last_used_step.is_none_or(|last| *step as i64 > last))
+        .find(|step| {
+            let expected = totp.generate(step * TOTP_STEP);
+            constant_time_eq(expected.as_bytes(), code.as_bytes())
+        })
+        .map(|step| step as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn totp() -> TOTP {
+        build_totp(&generate_secret(), "user@example.com").unwrap()
+    }
+
+    #[test]
+    fn accepts_the_neighbour_steps() {
+        let totp = totp();
+        let step = (NOW / TOTP_STEP) as i64;
+
+        for skew in [-1, 0, 1] {
+            let code = totp.generate(NOW.wrapping_add_signed(skew * TOTP_STEP as i64));
+            assert_eq!(verify_code_at(&totp, &code, None, NOW), Some(step + skew));
+        }
+
+        for skew in [-2, 2] {
+            let code = totp.generate(NOW.wrapping_add_signed(skew * TOTP_STEP as i64));
+            assert_eq!(verify_code_at(&totp, &code, None, NOW), None);
+        }
+    }
+
+    #[test]
+    fn rejects_used_steps() {
+        let totp = totp();
+        let step = (NOW / TOTP_STEP) as i64;
+        let code = totp.generate(NOW);
+
+        assert_eq!(
+            verify_code_at(&totp, &code, Some(step - 1), NOW),
+            Some(step)
+        );
+        assert_eq!(verify_code_at(&totp, &code, Some(step), NOW), None);
+    }
+
+    #[test]
+    fn ignores_spaces_and_rejects_wrong_codes() {
+        let totp = totp();
+        let code = totp.generate(NOW);
+        let spaced = format!(" {} {} ", &code[..3], &code[3..]);
+
+        assert!(verify_code_at(&totp, &spaced, None, NOW).is_some());
+        assert!(verify_code_at(&totp, "", None, NOW).is_none());
+        assert!(verify_code_at(&totp, &code[..5], None, NOW).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_secrets() {
+        assert!(build_totp("not base32!", "user@example.com").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+/// Returns a short description of the browser and operating system of an user agent.
+///
+/// This only recognizes the most common browsers, the order of the checks matters
+/// because most browsers also include the name of the others in the user agent.
+pub fn describe_user_agent(user_agent: &str) -> String {
+    let browser = if user_agent.contains("Edg/") {
+        "Edge"
+    } else if user_agent.contains("OPR/") || user_agent.contains("Opera") {
+        "Opera"
+    } else if user_agent.contains("Firefox/") {
+        "Firefox"
+    } else if user_agent.contains("Chrome/") || user_agent.contains("CriOS/") {
+        "Chrome"
+    } else if user_agent.contains("Safari/") {
+        "Safari"
+    } else if user_agent.starts_with("curl/") {
+        "curl"
+    } else {
+        "Unknown browser"
+    };
+
+    let os = if user_agent.contains("Android") {
+        "Android"
+    } else if user_agent.contains("iPhone") || user_agent.contains("iPad") {
+        "iOS"
+    } else if user_agent.contains("Windows") {
+        "Windows"
+    } else if user_agent.contains("Mac OS X") || user_agent.contains("Macintosh") {
+        "macOS"
+    } else if user_agent.contains("CrOS") {
+        "ChromeOS"
+    } else if user_agent.contains("Linux") {
+        "Linux"
+    } else {
+        return browser.to_owned();
+    };
+
+    format!("{browser} on {os}")
+}
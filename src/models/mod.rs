@@ -1,21 +1,134 @@
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
 pub struct User {
     pub id: Uuid,
     pub account_id: String,
+    #[sqlx(try_from = "String")]
     pub provider: AuthProvider,
     pub username: String,
     pub image_url: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
 pub struct UserSession {
-    pub id: Uuid,
+    // Only the hash of the token in the session cookie is stored
+    #[serde(skip_serializing)]
+    pub token_hash: String,
     pub user_id: Uuid,
     pub created_at: NaiveDateTime,
     pub expires_at: NaiveDateTime,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub second_factor_pending: bool,
+    pub persistent: bool,
+}
+
+/// An user with the details displayed to the admins.
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct UserSummary {
+    pub id: Uuid,
+    pub account_id: String,
+    #[sqlx(try_from = "String")]
+    pub provider: AuthProvider,
+    pub username: String,
+    pub image_url: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub last_login_at: Option<NaiveDateTime>,
+    pub disabled: bool,
+}
+
+/// A session as displayed to the users, the session id is not exposed because it's the session secret.
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct UserSessionInfo {
+    pub handle: i64,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub second_factor_pending: bool,
+    pub current: bool,
+}
+
+impl UserSessionInfo {
+    /// Returns the browser and operating system of the session, like `Firefox on Linux`.
+    pub fn device(&self) -> String {
+        match &self.user_agent {
+            Some(user_agent) => crate::misc::user_agent::describe_user_agent(user_agent),
+            None => "Unknown device".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct UserPasskey {
+    pub id: String,
+    pub user_id: Uuid,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UserTotp {
+    pub secret: String,
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UserCredential {
+    pub user_id: Uuid,
+    pub password_hash: String,
+    pub failed_attempts: i64,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Role {
+    pub id: i64,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+}
+
+impl Role {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Permission {
+    ReadUsers,
+    WriteUsers,
+    WriteSessions,
+    WriteRoles,
+}
+
+impl std::str::FromStr for Permission {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "users:read" => Ok(Permission::ReadUsers),
+            "users:write" => Ok(Permission::WriteUsers),
+            "sessions:write" => Ok(Permission::WriteSessions),
+            "roles:write" => Ok(Permission::WriteRoles),
+            _ => Err(anyhow::anyhow!("Unknown permission: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::ReadUsers => write!(f, "users:read"),
+            Permission::WriteUsers => write!(f, "users:write"),
+            Permission::WriteSessions => write!(f, "sessions:write"),
+            Permission::WriteRoles => write!(f, "roles:write"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
@@ -25,9 +138,19 @@ struct UnknownProvider {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 pub enum AuthProvider {
+    #[cfg(feature = "google")]
     Google,
+    #[cfg(feature = "github")]
     Github,
+    #[cfg(feature = "discord")]
     Discord,
+    #[cfg(feature = "oidc")]
+    Oidc,
+    #[cfg(feature = "mock")]
+    Mock,
+    Email,
+    Local,
+    Guest,
 
     // This variant should not be constructed
     #[allow(private_interfaces)]
@@ -37,20 +160,55 @@ pub enum AuthProvider {
 impl From<String> for AuthProvider {
     fn from(value: String) -> Self {
         match value.as_str() {
+            #[cfg(feature = "google")]
             "google" => AuthProvider::Google,
+            #[cfg(feature = "github")]
             "github" => AuthProvider::Github,
+            #[cfg(feature = "discord")]
             "discord" => AuthProvider::Discord,
+            #[cfg(feature = "oidc")]
+            "oidc" => AuthProvider::Oidc,
+            #[cfg(feature = "mock")]
+            "mock" => AuthProvider::Mock,
+            "email" => AuthProvider::Email,
+            "local" => AuthProvider::Local,
+            "guest" => AuthProvider::Guest,
             _ => AuthProvider::Unknown(UnknownProvider { _priv: () }),
         }
     }
 }
 
+impl AuthProvider {
+    /// Parses a provider account like `github:1234`, the provider must be enabled in the build.
+    pub fn parse_account(value: &str) -> Option<(AuthProvider, String)> {
+        let (name, account_id) = value.split_once(':')?;
+        let provider = AuthProvider::from(name.to_owned());
+
+        // Unknown providers are displayed differently than their name
+        if provider.to_string() != name || account_id.is_empty() {
+            return None;
+        }
+
+        Some((provider, account_id.to_owned()))
+    }
+}
+
 impl std::fmt::Display for AuthProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            #[cfg(feature = "google")]
             AuthProvider::Google => write!(f, "google"),
+            #[cfg(feature = "github")]
             AuthProvider::Github => write!(f, "github"),
+            #[cfg(feature = "discord")]
             AuthProvider::Discord => write!(f, "discord"),
+            #[cfg(feature = "oidc")]
+            AuthProvider::Oidc => write!(f, "oidc"),
+            #[cfg(feature = "mock")]
+            AuthProvider::Mock => write!(f, "mock"),
+            AuthProvider::Email => write!(f, "email"),
+            AuthProvider::Local => write!(f, "local"),
+            AuthProvider::Guest => write!(f, "guest"),
             _ => write!(f, "unknown provider"),
         }
     }
@@ -0,0 +1,88 @@
+use anyhow::Context;
+use axum::async_trait;
+
+use super::{LoginButton, OAuthProvider, ProviderUser};
+use crate::{config::OAuthClientConfig, models::AuthProvider};
+
+//  Checkout available fields on: https://discord.com/developers/docs/resources/user
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct DiscordUser {
+    id: String,
+    username: String,
+    email: Option<String>,
+
+    // For get the actual image we need to use: "https://cdn.discordapp.com/avatars/{id}/{avatar_hash}.png"
+    #[serde(rename = "avatar")]
+    avatar_hash: String,
+}
+
+/// Sign in with Discord.
+#[derive(Debug, Clone)]
+pub struct DiscordProvider {
+    credentials: OAuthClientConfig,
+}
+
+impl DiscordProvider {
+    pub fn new(credentials: OAuthClientConfig) -> Self {
+        DiscordProvider { credentials }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for DiscordProvider {
+    fn provider(&self) -> AuthProvider {
+        AuthProvider::Discord
+    }
+
+    fn credentials(&self) -> &OAuthClientConfig {
+        &self.credentials
+    }
+
+    fn auth_url(&self) -> &str {
+        "https://discord.com/oauth2/authorize"
+    }
+
+    fn token_url(&self) -> &str {
+        "https://discord.com/api/oauth2/token"
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec!["identify".to_owned()]
+    }
+
+    fn login_button(&self) -> LoginButton {
+        LoginButton {
+            label: "Discord".to_owned(),
+            icon_url: Some("/public/images/discord-logo.png".to_owned()),
+            icon_class: None,
+        }
+    }
+
+    async fn get_user(
+        &self,
+        http_client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<ProviderUser, anyhow::Error> {
+        let discord_user = http_client
+            .get("https://discord.com/api/users/@me")
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .context("Failed to get user info")?
+            .json::<DiscordUser>()
+            .await
+            .context("Failed to convert user info to Json")?;
+
+        let image_url = format!(
+            "https://cdn.discordapp.com/avatars/{account_id}/{avatar_hash}.png",
+            account_id = discord_user.id,
+            avatar_hash = discord_user.avatar_hash
+        );
+
+        Ok(ProviderUser {
+            account_id: discord_user.id,
+            username: discord_user.username,
+            image_url: Some(image_url),
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::Context;
+use axum::async_trait;
+
+use super::{LoginButton, OAuthProvider, ProviderUser};
+use crate::{config::OAuthClientConfig, models::AuthProvider};
+
+// Checkout available fields on: https://docs.github.com/en/rest/users/users?apiVersion=2022-11-28#get-the-authenticated-user
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct GithubUser {
+    id: u64,
+    name: Option<String>,
+    email: Option<String>,
+    avatar_url: String,
+}
+
+/// Sign in with Github.
+#[derive(Debug, Clone)]
+pub struct GithubProvider {
+    credentials: OAuthClientConfig,
+}
+
+impl GithubProvider {
+    pub fn new(credentials: OAuthClientConfig) -> Self {
+        GithubProvider { credentials }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for GithubProvider {
+    fn provider(&self) -> AuthProvider {
+        AuthProvider::Github
+    }
+
+    fn credentials(&self) -> &OAuthClientConfig {
+        &self.credentials
+    }
+
+    fn auth_url(&self) -> &str {
+        "https://github.com/login/oauth/authorize"
+    }
+
+    fn token_url(&self) -> &str {
+        "https://github.com/login/oauth/access_token"
+    }
+
+    fn login_button(&self) -> LoginButton {
+        LoginButton {
+            label: "Github".to_owned(),
+            icon_url: Some("/public/images/github-logo.png".to_owned()),
+            icon_class: Some("dark:invert-0 invert".to_owned()),
+        }
+    }
+
+    async fn get_user(
+        &self,
+        http_client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<ProviderUser, anyhow::Error> {
+        // Github requires an user agent, the shared http client sets it
+        let github_user = http_client
+            .get("https://api.github.com/user")
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .context("Failed to get user info")?
+            .json::<GithubUser>()
+            .await
+            .context("Failed to convert user info to Json")?;
+
+        let username = github_user
+            .name
+            .or(github_user.email)
+            .unwrap_or_else(|| "<unknown>".to_owned());
+
+        Ok(ProviderUser {
+            account_id: github_user.id.to_string(),
+            username,
+            image_url: Some(github_user.avatar_url),
+        })
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::Context;
+use axum::async_trait;
+
+use super::{LoginButton, OAuthProvider, ProviderUser};
+use crate::{config::OAuthClientConfig, models::AuthProvider};
+
+//  Checkout available fields on: https://developers.google.com/identity/openid-connect/openid-connect
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct GoogleUser {
+    sub: String,
+    name: String,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    picture: String,
+}
+
+/// Sign in with Google.
+#[derive(Debug, Clone)]
+pub struct GoogleProvider {
+    credentials: OAuthClientConfig,
+}
+
+impl GoogleProvider {
+    pub fn new(credentials: OAuthClientConfig) -> Self {
+        GoogleProvider { credentials }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for GoogleProvider {
+    fn provider(&self) -> AuthProvider {
+        AuthProvider::Google
+    }
+
+    fn credentials(&self) -> &OAuthClientConfig {
+        &self.credentials
+    }
+
+    fn auth_url(&self) -> &str {
+        "https://accounts.google.com/o/oauth2/v2/auth"
+    }
+
+    fn token_url(&self) -> &str {
+        "https://www.googleapis.com/oauth2/v3/token"
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec!["https://www.googleapis.com/auth/userinfo.profile".to_owned()]
+    }
+
+    fn login_button(&self) -> LoginButton {
+        LoginButton {
+            label: "Google".to_owned(),
+            icon_url: Some("/public/images/google-logo.png".to_owned()),
+            icon_class: None,
+        }
+    }
+
+    async fn get_user(
+        &self,
+        http_client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<ProviderUser, anyhow::Error> {
+        let google_user = http_client
+            .get("https://www.googleapis.com/oauth2/v3/userinfo")
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .context("Failed to get user info")?
+            .json::<GoogleUser>()
+            .await
+            .context("Failed to convert user info to Json")?;
+
+        Ok(ProviderUser {
+            account_id: google_user.sub,
+            username: google_user.name,
+            image_url: Some(google_user.picture),
+        })
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    async_trait,
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use reqwest::Url;
+
+use super::{LoginButton, OAuthProvider, ProviderUser};
+use crate::{
+    config::{OAuthClientConfig, Secret},
+    misc::Theme,
+    models::{AuthProvider, User},
+    routes::filters,
+    server::UserTheme,
+};
+
+/// Sign in with any username, without an account in a real provider.
+///
+/// The provider serves its own authorization and token endpoints, so the server must be able
+/// to reach itself at the base url. This is only meant for development and tests.
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    credentials: OAuthClientConfig,
+    auth_url: String,
+    token_url: String,
+    redirect_url: Arc<str>,
+}
+
+impl MockProvider {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+
+        MockProvider {
+            credentials: OAuthClientConfig {
+                client_id: "mock".to_owned(),
+                client_secret: Secret::new("mock".to_owned()),
+            },
+            auth_url: format!("{base_url}/api/auth/mock/authorize"),
+            token_url: format!("{base_url}/api/auth/mock/token"),
+            redirect_url: format!("{base_url}/api/auth/mock/callback").into(),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for MockProvider {
+    fn provider(&self) -> AuthProvider {
+        AuthProvider::Mock
+    }
+
+    fn credentials(&self) -> &OAuthClientConfig {
+        &self.credentials
+    }
+
+    fn auth_url(&self) -> &str {
+        &self.auth_url
+    }
+
+    fn token_url(&self) -> &str {
+        &self.token_url
+    }
+
+    fn login_button(&self) -> LoginButton {
+        LoginButton {
+            label: "Mock".to_owned(),
+            icon_url: None,
+            icon_class: None,
+        }
+    }
+
+    fn router(&self) -> Router {
+        let redirect_url = self.redirect_url.clone();
+
+        Router::new()
+            .route(
+                "/api/auth/mock/authorize",
+                get(move |theme, query| authorize(redirect_url, theme, query)),
+            )
+            .route("/api/auth/mock/token", post(token))
+    }
+
+    async fn get_user(
+        &self,
+        _http_client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<ProviderUser, anyhow::Error> {
+        // The access token is the username that was entered
+        if !is_valid_username(access_token) {
+            anyhow::bail!("Invalid mock access token");
+        }
+
+        Ok(ProviderUser {
+            account_id: access_token.to_owned(),
+            username: access_token.to_owned(),
+            image_url: None,
+        })
+    }
+}
+
+fn is_valid_username(username: &str) -> bool {
+    (1..=32).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[derive(Template)]
+#[template(path = "mock_login.html")]
+struct MockLoginTemplate {
+    theme: Theme,
+    user: Option<User>,
+    redirect_uri: String,
+    state: String,
+    error: Option<&'static str>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AuthorizeQuery {
+    redirect_uri: String,
+    state: String,
+    username: Option<String>,
+}
+
+/// Asks for the username to sign in with, then redirects back with it as the code.
+async fn authorize(
+    redirect_url: Arc<str>,
+    UserTheme(theme): UserTheme,
+    Query(query): Query<AuthorizeQuery>,
+) -> Response {
+    // Only redirect to the callback of this server
+    if query.redirect_uri != *redirect_url {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let username = query.username.as_deref().map(str::trim);
+    if let Some(username) = username.filter(|x| is_valid_username(x)) {
+        let mut redirect_url = match Url::parse(&redirect_url) {
+            Ok(url) => url,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+
+        redirect_url
+            .query_pairs_mut()
+            .append_pair("code", username)
+            .append_pair("state", &query.state);
+
+        return Redirect::to(redirect_url.as_str()).into_response();
+    }
+
+    MockLoginTemplate {
+        theme: theme.unwrap_or_default(),
+        user: None,
+        redirect_uri: query.redirect_uri,
+        state: query.state,
+        error: username.map(|_| "Use 1 to 32 letters, numbers, '-', '_' or '.'"),
+    }
+    .into_response()
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TokenRequest {
+    code: String,
+}
+
+/// Exchanges the code for an access token, which is the same username.
+async fn token(Form(request): Form<TokenRequest>) -> Response {
+    if !is_valid_username(&request.code) {
+        let error = serde_json::json!({ "error": "invalid_grant" });
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+
+    Json(serde_json::json!({
+        "access_token": request.code,
+        "token_type": "bearer",
+    }))
+    .into_response()
+}
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{async_trait, Router};
+use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+
+use crate::{config::OAuthClientConfig, models::AuthProvider};
+
+#[cfg(feature = "discord")]
+mod discord;
+#[cfg(feature = "github")]
+mod github;
+#[cfg(feature = "google")]
+mod google;
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "oidc")]
+mod oidc;
+
+#[cfg(feature = "discord")]
+pub use discord::DiscordProvider;
+#[cfg(feature = "github")]
+pub use github::GithubProvider;
+#[cfg(feature = "google")]
+pub use google::GoogleProvider;
+#[cfg(feature = "mock")]
+pub use mock::MockProvider;
+#[cfg(feature = "oidc")]
+pub use oidc::{OidcConfig, OidcProvider};
+
+/// An user as returned by a provider, before it's linked to an user of the app.
+#[derive(Debug, Clone)]
+pub struct ProviderUser {
+    /// The id of the account in the provider, it must not change.
+    pub account_id: String,
+    pub username: String,
+    pub image_url: Option<String>,
+}
+
+/// How the provider is displayed in the login page.
+#[derive(Debug, Clone)]
+pub struct LoginButton {
+    pub label: String,
+    pub icon_url: Option<String>,
+    /// Extra classes of the icon, like inverting a dark logo in the light theme.
+    pub icon_class: Option<String>,
+}
+
+/// A provider users can sign in with using oauth2 and PKCE.
+///
+/// The routes are `/api/auth/{provider}/login` and `/api/auth/{provider}/callback`, where `provider`
+/// is the name of [`OAuthProvider::provider`], the callback must be registered in the provider.
+#[async_trait]
+pub trait OAuthProvider: Send + Sync + 'static {
+    fn provider(&self) -> AuthProvider;
+
+    fn credentials(&self) -> &OAuthClientConfig;
+
+    fn auth_url(&self) -> &str;
+
+    fn token_url(&self) -> &str;
+
+    /// The scopes needed to read the user, none by default.
+    fn scopes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn login_button(&self) -> LoginButton;
+
+    /// Extra routes served by the provider itself, merged with its login and callback routes.
+    fn router(&self) -> Router {
+        Router::new()
+    }
+
+    /// Returns the user that owns the access token.
+    async fn get_user(
+        &self,
+        http_client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<ProviderUser, anyhow::Error>;
+}
+
+/// A provider with its oauth client, the client is created once when the router is built.
+pub struct ProviderClient {
+    pub provider: Arc<dyn OAuthProvider>,
+    pub client: BasicClient,
+}
+
+impl ProviderClient {
+    pub fn new(provider: Arc<dyn OAuthProvider>, base_url: &str) -> Result<Self, anyhow::Error> {
+        let credentials = provider.credentials();
+        let client_id = ClientId::new(credentials.client_id.clone());
+        let client_secret = ClientSecret::new(credentials.client_secret.expose().to_owned());
+
+        let auth_url = AuthUrl::new(provider.auth_url().to_owned())
+            .context("Invalid authorization endpoint URL")?;
+        let token_url =
+            TokenUrl::new(provider.token_url().to_owned()).context("Invalid token endpoint URL")?;
+
+        let name = provider.provider();
+        let redirect_url = RedirectUrl::new(format!("{base_url}/api/auth/{name}/callback"))
+            .context("Invalid redirect url")?;
+
+        let client = BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url))
+            .set_redirect_uri(redirect_url);
+
+        Ok(ProviderClient { provider, client })
+    }
+}
+
+/// The providers registered in the router, in the order their buttons are displayed.
+pub type SharedProviders = Arc<Vec<Arc<ProviderClient>>>;
@@ -0,0 +1,184 @@
+use anyhow::Context;
+use axum::async_trait;
+use reqwest::Url;
+
+use super::{LoginButton, OAuthProvider, ProviderUser};
+use crate::{
+    config::{ConfigReader, OAuthClientConfig},
+    models::AuthProvider,
+};
+
+/// The settings of an OpenID Connect provider, like Keycloak or Authentik.
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// The issuer of the provider, its endpoints are read from `{issuer_url}/.well-known/openid-configuration`.
+    pub issuer_url: String,
+    pub credentials: OAuthClientConfig,
+    /// The name displayed in the login button.
+    pub name: String,
+    pub icon_url: Option<String>,
+}
+
+impl OidcConfig {
+    /// Reads `OIDC_ISSUER_URL`, `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET`, `OIDC_NAME` and `OIDC_ICON_URL`.
+    pub fn from_config(reader: &mut ConfigReader) -> Option<Self> {
+        let credentials = OAuthClientConfig::from_config(reader, "OIDC");
+        let issuer_url = reader.get("OIDC_ISSUER_URL");
+
+        let (credentials, issuer_url) = match (credentials, issuer_url) {
+            (Some(credentials), Some(issuer_url)) => (credentials, issuer_url),
+            (Some(_), None) => {
+                reader.error("'OIDC_ISSUER_URL' is not set, but 'OIDC_CLIENT_ID' is");
+                return None;
+            }
+            (None, Some(_)) if reader.get("OIDC_CLIENT_ID").is_none() => {
+                reader.error("'OIDC_CLIENT_ID' is not set, but 'OIDC_ISSUER_URL' is");
+                return None;
+            }
+            (None, _) => return None,
+        };
+
+        if !Url::parse(&issuer_url).is_ok_and(|x| x.host().is_some()) {
+            reader.error(format!(
+                "Invalid 'OIDC_ISSUER_URL', expected an url with a host: {issuer_url}"
+            ));
+            return None;
+        }
+
+        Some(OidcConfig {
+            issuer_url: issuer_url.trim_end_matches('/').to_owned(),
+            credentials,
+            name: reader
+                .get("OIDC_NAME")
+                .unwrap_or_else(|| "OpenID Connect".to_owned()),
+            icon_url: reader.get("OIDC_ICON_URL"),
+        })
+    }
+}
+
+// Checkout available fields on: https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+#[derive(serde::Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+// Checkout available fields on: https://openid.net/specs/openid-connect-core-1_0.html#StandardClaims
+#[derive(serde::Deserialize)]
+struct OidcUser {
+    sub: String,
+    name: Option<String>,
+    preferred_username: Option<String>,
+    picture: Option<String>,
+}
+
+/// Sign in with any OpenID Connect provider, the user is read from its userinfo endpoint.
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    config: OidcConfig,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+impl OidcProvider {
+    /// Reads the endpoints of the provider from its discovery document.
+    pub async fn discover(
+        http_client: &reqwest::Client,
+        config: OidcConfig,
+    ) -> Result<Self, anyhow::Error> {
+        let metadata = http_client
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                config.issuer_url
+            ))
+            .send()
+            .await
+            .context("Failed to get discovery document")?
+            .error_for_status()
+            .context("Failed to get discovery document")?
+            .json::<ProviderMetadata>()
+            .await
+            .context("Failed to convert discovery document to Json")?;
+
+        // The document must be about the issuer it was requested from
+        if metadata.issuer.trim_end_matches('/') != config.issuer_url {
+            anyhow::bail!(
+                "The discovery document is for the issuer '{}', expected '{}'",
+                metadata.issuer,
+                config.issuer_url
+            );
+        }
+
+        let userinfo_endpoint = metadata
+            .userinfo_endpoint
+            .context("The provider doesn't have an userinfo endpoint")?;
+
+        Ok(OidcProvider {
+            config,
+            authorization_endpoint: metadata.authorization_endpoint,
+            token_endpoint: metadata.token_endpoint,
+            userinfo_endpoint,
+        })
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for OidcProvider {
+    fn provider(&self) -> AuthProvider {
+        AuthProvider::Oidc
+    }
+
+    fn credentials(&self) -> &OAuthClientConfig {
+        &self.config.credentials
+    }
+
+    fn auth_url(&self) -> &str {
+        &self.authorization_endpoint
+    }
+
+    fn token_url(&self) -> &str {
+        &self.token_endpoint
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec!["openid".to_owned(), "profile".to_owned()]
+    }
+
+    fn login_button(&self) -> LoginButton {
+        LoginButton {
+            label: self.config.name.clone(),
+            icon_url: self.config.icon_url.clone(),
+            icon_class: None,
+        }
+    }
+
+    async fn get_user(
+        &self,
+        http_client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<ProviderUser, anyhow::Error> {
+        let oidc_user = http_client
+            .get(&self.userinfo_endpoint)
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .context("Failed to get user info")?
+            .json::<OidcUser>()
+            .await
+            .context("Failed to convert user info to Json")?;
+
+        let username = oidc_user
+            .name
+            .or(oidc_user.preferred_username)
+            .unwrap_or_else(|| oidc_user.sub.clone());
+
+        Ok(ProviderUser {
+            account_id: oidc_user.sub,
+            username,
+            image_url: oidc_user.picture,
+        })
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::{
+    misc::error::AppError,
+    models::Permission,
+    server::{Admin, AppState, CurrentUser, RequireAuth, RequireRole, RoleName, SessionCache},
+    session_store::SharedSessionStore,
+};
+
+pub fn admin_router(state: &AppState) -> Router<AppState> {
+    let roles_router = Router::new()
+        .route(
+            "/api/admin/users/:user_id/roles/:role_name",
+            post(grant_role),
+        )
+        .route(
+            "/api/admin/users/:user_id/roles/:role_name/delete",
+            post(revoke_role),
+        )
+        .route_layer(
+            RequireAuth::new(state)
+                .role(Admin::NAME)
+                .permission(Permission::WriteRoles),
+        );
+
+    let sessions_router = Router::new()
+        .route(
+            "/api/admin/users/:user_id/sessions/delete",
+            post(revoke_user_sessions),
+        )
+        .route(
+            "/api/admin/users/:user_id/sessions/:handle/delete",
+            post(revoke_user_session),
+        )
+        .route_layer(
+            RequireAuth::new(state)
+                .role(Admin::NAME)
+                .permission(Permission::WriteSessions),
+        );
+
+    let users_router = Router::new()
+        .route("/api/admin/users/:user_id/disable", post(disable_user))
+        .route("/api/admin/users/:user_id/enable", post(enable_user))
+        .route("/api/admin/users/:user_id/delete", post(delete_user))
+        .route_layer(
+            RequireAuth::new(state)
+                .role(Admin::NAME)
+                .permission(Permission::WriteUsers),
+        );
+
+    Router::new()
+        .route("/api/admin/roles", get(get_roles))
+        .merge(roles_router)
+        .merge(sessions_router)
+        .merge(users_router)
+}
+
+async fn get_roles(
+    _: RequireRole<Admin>,
+    State(pool): State<DbPool>,
+) -> Result<impl IntoResponse, AppError> {
+    let roles = crate::db::get_roles(&pool)
+        .await
+        .context("Failed to get roles")?;
+
+    Ok(Json(roles))
+}
+
+async fn grant_role(
+    current_user: CurrentUser,
+    State(pool): State<DbPool>,
+    State(session_cache): State<SessionCache>,
+    Path((user_id, role_name)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let granted = crate::db::grant_user_role(&pool, user_id, &role_name)
+        .await
+        .context("Failed to grant role")?;
+
+    if !granted {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    session_cache.invalidate_user(user_id);
+
+    tracing::info!(
+        "user '{}' gave the '{role_name}' role to user '{user_id}'",
+        current_user.user.id
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn revoke_role(
+    current_user: CurrentUser,
+    State(pool): State<DbPool>,
+    State(session_cache): State<SessionCache>,
+    Path((user_id, role_name)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let revoked = crate::db::revoke_user_role(&pool, user_id, &role_name)
+        .await
+        .context("Failed to revoke role")?;
+
+    if !revoked {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    session_cache.invalidate_user(user_id);
+
+    tracing::info!(
+        "user '{}' removed the '{role_name}' role from user '{user_id}'",
+        current_user.user.id
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn revoke_user_sessions(
+    current_user: CurrentUser,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let deleted = session_store
+        .delete_for_user(user_id, None)
+        .await
+        .context("Failed to delete user sessions")?;
+
+    session_cache.invalidate_user(user_id);
+
+    tracing::info!(
+        "user '{}' revoked {deleted} sessions of user '{user_id}'",
+        current_user.user.id
+    );
+
+    Ok(Redirect::to(&format!("/admin/users/{user_id}")))
+}
+
+async fn revoke_user_session(
+    current_user: CurrentUser,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Path((user_id, handle)): Path<(Uuid, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let deleted = session_store
+        .delete_by_handle(user_id, handle)
+        .await
+        .context("Failed to delete user session")?;
+
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    session_cache.invalidate_user(user_id);
+    tracing::info!(
+        "user '{}' revoked a session of user '{user_id}'",
+        current_user.user.id
+    );
+
+    Ok(Redirect::to(&format!("/admin/users/{user_id}")).into_response())
+}
+
+async fn set_user_disabled(
+    current_user: CurrentUser,
+    pool: DbPool,
+    session_cache: SessionCache,
+    session_store: SharedSessionStore,
+    user_id: Uuid,
+    disabled: bool,
+) -> Result<Response, AppError> {
+    // Prevent admins from locking themselves out
+    if current_user.user.id == user_id {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let updated = crate::db::set_user_disabled(&pool, user_id, disabled)
+        .await
+        .context("Failed to update user")?;
+
+    if !updated {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    // Disabling an user also signs it out everywhere
+    if disabled {
+        session_store
+            .delete_for_user(user_id, None)
+            .await
+            .context("Failed to delete user sessions")?;
+    }
+
+    session_cache.invalidate_user(user_id);
+
+    let action = if disabled { "disabled" } else { "enabled" };
+    tracing::info!("user '{}' {action} user '{user_id}'", current_user.user.id);
+
+    Ok(Redirect::to(&format!("/admin/users/{user_id}")).into_response())
+}
+
+async fn disable_user(
+    current_user: CurrentUser,
+    State(pool): State<DbPool>,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    set_user_disabled(
+        current_user,
+        pool,
+        session_cache,
+        session_store,
+        user_id,
+        true,
+    )
+    .await
+}
+
+async fn enable_user(
+    current_user: CurrentUser,
+    State(pool): State<DbPool>,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    set_user_disabled(
+        current_user,
+        pool,
+        session_cache,
+        session_store,
+        user_id,
+        false,
+    )
+    .await
+}
+
+async fn delete_user(
+    current_user: CurrentUser,
+    State(pool): State<DbPool>,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    if current_user.user.id == user_id {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    session_store
+        .delete_for_user(user_id, None)
+        .await
+        .context("Failed to delete user sessions")?;
+
+    let deleted = crate::db::delete_user(&pool, user_id)
+        .await
+        .context("Failed to delete user")?;
+
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    session_cache.invalidate_user(user_id);
+    tracing::info!("user '{}' deleted user '{user_id}'", current_user.user.id);
+    Ok(Redirect::to("/admin").into_response())
+}
@@ -0,0 +1,166 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    routing::post,
+    Form, Router,
+};
+
+use crate::db::DbPool;
+use crate::{
+    config::SharedAuthConfig,
+    constants::{
+        EMAIL_LOGIN_MAX_PER_ADDRESS, EMAIL_LOGIN_MAX_PER_IP, EMAIL_LOGIN_RATE_LIMIT_WINDOW,
+        EMAIL_LOGIN_TOKEN_DURATION,
+    },
+    mailer::{Email, SharedMailer},
+    misc::{
+        error::AppError,
+        token::{generate_token, hash_token},
+    },
+    models::AuthProvider,
+    server::{AppState, ClientInfo, LoginContext, RateLimiter},
+};
+use axum_extra::extract::cookie::CookieJar;
+
+pub fn email_auth_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/auth/email/login", post(login))
+        .route("/api/auth/email/callback", post(callback))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LoginRequest {
+    email: String,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+fn normalize_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = email.split_once('@')?;
+
+    if local.is_empty()
+        || domain.is_empty()
+        || email.len() > 254
+        || email.chars().any(|c| c.is_whitespace())
+    {
+        return None;
+    }
+
+    Some(email)
+}
+
+async fn login(
+    client: ClientInfo,
+    State(pool): State<DbPool>,
+    State(mailer): State<Option<SharedMailer>>,
+    State(config): State<SharedAuthConfig>,
+    State(rate_limiter): State<RateLimiter>,
+    Form(request): Form<LoginRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(mailer) = mailer else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let Some(email) = normalize_email(&request.email) else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    // So the form can't be used to flood an inbox
+    let window = EMAIL_LOGIN_RATE_LIMIT_WINDOW;
+    let ip_allowed = client.ip_address.as_ref().is_none_or(|ip| {
+        rate_limiter.check(
+            &format!("email_login_ip:{ip}"),
+            EMAIL_LOGIN_MAX_PER_IP,
+            window,
+        )
+    });
+
+    if !ip_allowed
+        || !rate_limiter.check(
+            &format!("email_login:{email}"),
+            EMAIL_LOGIN_MAX_PER_ADDRESS,
+            window,
+        )
+    {
+        tracing::warn!("too many sign-in emails were requested");
+        return Ok(StatusCode::TOO_MANY_REQUESTS.into_response());
+    }
+
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+
+    crate::db::create_email_login_token(&pool, &email, &token_hash, EMAIL_LOGIN_TOKEN_DURATION)
+        .await
+        .context("Failed to create email login token")?;
+
+    let base_url = &config.base_url;
+    let login_url = format!("{base_url}/login/email?token={token}");
+    let minutes = EMAIL_LOGIN_TOKEN_DURATION.as_secs() / 60;
+
+    mailer
+        .send(Email {
+            to: email,
+            subject: "Your sign-in link".to_owned(),
+            body: format!(
+                "Use this link to sign in, it expires in {minutes} minutes and can only be used once:\n\n{login_url}\n\nIf you did not request this email you can safely ignore it."
+            ),
+        })
+        .await
+        .context("Failed to send sign-in email")?;
+
+    // The link is expected to be opened in the same browser
+    let max_age =
+        cookie::time::Duration::milliseconds(EMAIL_LOGIN_TOKEN_DURATION.as_millis() as i64);
+    let cookies = CookieJar::new().add(super::remember_me_cookie(request.remember_me, max_age));
+    Ok((cookies, Redirect::to("/login?email_sent=true")).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AuthRequest {
+    token: String,
+}
+
+/// Signs in with the token of the link, the link opens a page that posts it so the
+/// mail scanners that open the links don't use the token.
+async fn callback(
+    cookies: CookieJar,
+    login_context: LoginContext,
+    State(pool): State<DbPool>,
+    Form(request): Form<AuthRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let token_hash = hash_token(&request.token);
+    let email = crate::db::consume_email_login_token(&pool, &token_hash)
+        .await
+        .context("Failed to get email login token")?;
+
+    // The token was already used, expired or never existed
+    let Some(email) = email else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+
+    // Add user session
+    let user = super::find_or_create_user(
+        &pool,
+        &login_context,
+        &cookies,
+        AuthProvider::Email,
+        email.clone(),
+        email,
+        None,
+    )
+    .await
+    .context("Failed to get or create user")?;
+
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, user.id, false, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    let cookies = CookieJar::new().add(session_cookie);
+
+    let response = (cookies, redirect).into_response();
+    Ok(response)
+}
@@ -0,0 +1,70 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    routing::post,
+    Router,
+};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::{
+    constants::{GUEST_LOGIN_MAX_PER_IP, GUEST_LOGIN_RATE_LIMIT_WINDOW},
+    misc::error::AppError,
+    models::AuthProvider,
+    server::{AppState, CurrentUser, LoginContext, RateLimiter},
+};
+use axum_extra::extract::cookie::CookieJar;
+
+pub fn guest_auth_router() -> Router<AppState> {
+    Router::new().route("/api/auth/guest", post(login))
+}
+
+async fn login(
+    current_user: Option<CurrentUser>,
+    mut login_context: LoginContext,
+    State(pool): State<DbPool>,
+    State(rate_limiter): State<RateLimiter>,
+) -> Result<impl IntoResponse, AppError> {
+    // The user keeps the current session, a guest would only replace it
+    if current_user.is_some() {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    // Each guest is a new user, so they are limited to not fill the database
+    let allowed = login_context.client.ip_address.as_ref().is_none_or(|ip| {
+        rate_limiter.check(
+            &format!("guest_login_ip:{ip}"),
+            GUEST_LOGIN_MAX_PER_IP,
+            GUEST_LOGIN_RATE_LIMIT_WINDOW,
+        )
+    });
+
+    if !allowed {
+        tracing::warn!("too many guests were created");
+        return Ok(StatusCode::TOO_MANY_REQUESTS.into_response());
+    }
+
+    // Guests don't have an account in any provider, so we give them a random one
+    let account_id = Uuid::new_v4().to_string();
+    let user = crate::db::create_user(
+        &pool,
+        account_id,
+        AuthProvider::Guest,
+        "Guest".to_owned(),
+        None,
+    )
+    .await
+    .context("Failed to create guest user")?;
+
+    // The guest data is lost with the cookie, so it must not end when the browser is closed
+    login_context.remember_me = true;
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, user.id, false, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    let cookies = CookieJar::new().add(session_cookie);
+    Ok((cookies, redirect).into_response())
+}
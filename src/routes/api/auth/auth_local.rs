@@ -0,0 +1,272 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    routing::post,
+    Form, Router,
+};
+
+use crate::db::DbPool;
+use crate::{
+    config::ConfigReader,
+    constants::{COOKIE_AUTH_SESSION, LOGIN_LOCKOUT_DURATION, LOGIN_MAX_FAILED_ATTEMPTS},
+    misc::{
+        error::AppError,
+        password::{hash_password, validate_password, validate_username, verify_password},
+        token::hash_token,
+    },
+    models::AuthProvider,
+    server::{AppState, CurrentUser, LoginContext, SessionCache},
+    session_store::SharedSessionStore,
+};
+use axum_extra::extract::cookie::CookieJar;
+
+/// Whether users can register and sign in with an username and password,
+/// this is disabled unless `LOCAL_AUTH_ENABLED` is set to `true`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalAuthConfig {
+    pub enabled: bool,
+}
+
+impl LocalAuthConfig {
+    pub fn from_config(reader: &mut ConfigReader) -> Self {
+        let enabled = reader.flag("LOCAL_AUTH_ENABLED", false);
+
+        LocalAuthConfig { enabled }
+    }
+}
+
+pub fn local_auth_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/auth/local/register", post(register))
+        .route("/api/auth/local/login", post(login))
+        .route("/api/auth/local/password", post(change_password))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RegisterRequest {
+    username: String,
+    password: String,
+    password_confirmation: String,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+async fn register(
+    cookies: CookieJar,
+    mut login_context: LoginContext,
+    State(config): State<LocalAuthConfig>,
+    State(pool): State<DbPool>,
+    Form(request): Form<RegisterRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if !config.enabled {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let username = request.username.trim().to_owned();
+    let error = validate_username(&username)
+        .or_else(|| validate_password(&request.password))
+        .or_else(|| {
+            (request.password != request.password_confirmation).then_some("password_mismatch")
+        });
+
+    if let Some(error) = error {
+        return Ok(Redirect::to(&format!("/register?error={error}")).into_response());
+    }
+
+    let existing_user =
+        crate::db::get_user_by_account_id(&pool, AuthProvider::Local, username.to_lowercase())
+            .await
+            .context("Failed to get user")?;
+
+    if existing_user.is_some() {
+        return Ok(Redirect::to("/register?error=username_taken").into_response());
+    }
+
+    let password_hash = hash_password(request.password).await?;
+
+    // Like when signing in with a provider, the guest becomes the new user
+    let guest = super::get_current_guest(&pool, &login_context, &cookies)
+        .await
+        .context("Failed to get guest user")?;
+
+    let user = match guest {
+        Some(guest) => {
+            super::delete_guest_sessions(&login_context, guest.id)
+                .await
+                .context("Failed to delete guest sessions")?;
+
+            let user =
+                crate::db::upgrade_guest_to_local_user(&pool, guest.id, username, password_hash)
+                    .await
+                    .context("Failed to upgrade guest user")?;
+
+            tracing::info!("guest '{}' was upgraded to a local user", guest.id);
+            user
+        }
+        None => crate::db::create_local_user(&pool, username, password_hash)
+            .await
+            .context("Failed to create user")?,
+    };
+
+    super::bootstrap_admin(&pool, &login_context.config.admin_accounts, &user)
+        .await
+        .context("Failed to give the admin role")?;
+
+    login_context.remember_me = request.remember_me;
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, user.id, false, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    let cookies = CookieJar::new().add(session_cookie);
+    Ok((cookies, redirect).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LoginRequest {
+    username: String,
+    password: String,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+async fn login(
+    cookies: CookieJar,
+    mut login_context: LoginContext,
+    State(config): State<LocalAuthConfig>,
+    State(pool): State<DbPool>,
+    Form(request): Form<LoginRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if !config.enabled {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let mut credential = crate::db::get_user_credential_by_username(&pool, request.username.trim())
+        .await
+        .context("Failed to get user credential")?;
+
+    let now = chrono::offset::Utc::now().naive_utc();
+    let locked = credential
+        .as_ref()
+        .and_then(|x| x.locked_until)
+        .is_some_and(|x| x > now);
+
+    // The failed attempts start over once the lock expired
+    if let Some(credential) = credential
+        .as_mut()
+        .filter(|x| x.locked_until.is_some_and(|x| x <= now))
+    {
+        crate::db::reset_failed_logins(&pool, credential.user_id)
+            .await
+            .context("Failed to reset failed logins")?;
+
+        credential.failed_attempts = 0;
+        credential.locked_until = None;
+    }
+
+    let password_hash = credential.as_ref().map(|x| x.password_hash.clone());
+    let valid = verify_password(request.password, password_hash).await?;
+
+    // A locked account gets the same error as an username that doesn't exist
+    let credential = match credential {
+        Some(credential) if valid && !locked => credential,
+        Some(_) if locked => {
+            return Ok(Redirect::to("/login?local_error=invalid").into_response());
+        }
+        Some(credential) => {
+            crate::db::record_failed_login(
+                &pool,
+                credential.user_id,
+                LOGIN_MAX_FAILED_ATTEMPTS,
+                LOGIN_LOCKOUT_DURATION,
+            )
+            .await
+            .context("Failed to record failed login")?;
+
+            tracing::warn!("failed login attempt for user '{}'", credential.user_id);
+            return Ok(Redirect::to("/login?local_error=invalid").into_response());
+        }
+        None => return Ok(Redirect::to("/login?local_error=invalid").into_response()),
+    };
+
+    if credential.failed_attempts > 0 {
+        crate::db::reset_failed_logins(&pool, credential.user_id)
+            .await
+            .context("Failed to reset failed logins")?;
+    }
+
+    super::merge_current_guest(&pool, &login_context, &cookies, credential.user_id)
+        .await
+        .context("Failed to merge guest user")?;
+
+    login_context.remember_me = request.remember_me;
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, credential.user_id, false, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    let cookies = CookieJar::new().add(session_cookie);
+    Ok((cookies, redirect).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+    password_confirmation: String,
+}
+
+async fn change_password(
+    CurrentUser { user, .. }: CurrentUser,
+    cookies: CookieJar,
+    State(config): State<LocalAuthConfig>,
+    State(pool): State<DbPool>,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Form(request): Form<ChangePasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if !config.enabled {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let Some(credential) = crate::db::get_user_credential(&pool, user.id)
+        .await
+        .context("Failed to get user credential")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let valid = verify_password(request.current_password, Some(credential.password_hash)).await?;
+    if !valid {
+        return Ok(Redirect::to("/settings/password?error=invalid_password").into_response());
+    }
+
+    let error = validate_password(&request.new_password).or_else(|| {
+        (request.new_password != request.password_confirmation).then_some("password_mismatch")
+    });
+
+    if let Some(error) = error {
+        return Ok(Redirect::to(&format!("/settings/password?error={error}")).into_response());
+    }
+
+    let password_hash = hash_password(request.new_password).await?;
+    crate::db::update_user_password(&pool, user.id, password_hash)
+        .await
+        .context("Failed to update password")?;
+
+    // Sign out any other device that may know the old password
+    if let Some(session_cookie) = cookies.get(COOKIE_AUTH_SESSION) {
+        let token_hash = hash_token(session_cookie.value());
+        session_store
+            .delete_for_user(user.id, Some(&token_hash))
+            .await
+            .context("Failed to delete user sessions")?;
+
+        session_cache.invalidate_user(user.id);
+    }
+
+    tracing::info!("user '{}' changed the password", user.id);
+    Ok(Redirect::to("/settings/password?updated=true").into_response())
+}
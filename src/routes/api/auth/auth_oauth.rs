@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    routing::get,
+    Router,
+};
+
+use oauth2::TokenResponse;
+use oauth2::{AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, Scope};
+
+use crate::db::DbPool;
+use crate::{
+    constants::{COOKIE_AUTH_CODE_VERIFIER, COOKIE_AUTH_CSRF_STATE},
+    misc::{error::AppError, http::oauth_http_client},
+    providers::ProviderClient,
+    server::{AppState, LoginContext},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+
+/// The login and callback routes of each provider, like `/api/auth/github/login`.
+pub fn oauth_auth_router(state: &AppState) -> Router<AppState> {
+    let mut router = Router::new();
+
+    for provider in state.providers.iter() {
+        let name = provider.provider.provider();
+        let login_provider = provider.clone();
+        let callback_provider = provider.clone();
+
+        router = router
+            .route(
+                &format!("/api/auth/{name}/login"),
+                get(move |query| login(login_provider, query)),
+            )
+            .route(
+                &format!("/api/auth/{name}/callback"),
+                get(move |cookies, login_context, pool, http_client, query| {
+                    callback(
+                        callback_provider,
+                        cookies,
+                        login_context,
+                        pool,
+                        http_client,
+                        query,
+                    )
+                }),
+            )
+            .merge(provider.provider.router().with_state(()));
+    }
+
+    router
+}
+
+async fn login(
+    provider: Arc<ProviderClient>,
+    Query(query): Query<super::RememberMeQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
+
+    let scopes = provider.provider.scopes().into_iter().map(Scope::new);
+    let (authorize_url, csrf_state) = provider
+        .client
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(scopes)
+        .set_pkce_challenge(pkce_code_challenge)
+        .url();
+
+    // Set csrf, code verifier and remember me cookies, these are short lived cookies
+    let cookie_max_age = cookie::time::Duration::minutes(5);
+    let csrf_cookie: Cookie =
+        Cookie::build((COOKIE_AUTH_CSRF_STATE, csrf_state.secret().to_owned()))
+            .http_only(true)
+            .path("/")
+            .same_site(SameSite::Lax)
+            .max_age(cookie_max_age)
+            .into();
+
+    let code_verifier: Cookie = Cookie::build((
+        COOKIE_AUTH_CODE_VERIFIER,
+        pkce_code_verifier.secret().to_owned(),
+    ))
+    .http_only(true)
+    .path("/")
+    .same_site(SameSite::Lax)
+    .max_age(cookie_max_age)
+    .into();
+
+    let remember_me = super::remember_me_cookie(query.remember_me, cookie_max_age);
+    let cookies = CookieJar::new()
+        .add(csrf_cookie)
+        .add(code_verifier)
+        .add(remember_me);
+
+    Ok((cookies, Redirect::to(authorize_url.as_str())))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AuthRequest {
+    code: String,
+    state: String,
+}
+
+async fn callback(
+    provider: Arc<ProviderClient>,
+    cookies: CookieJar,
+    login_context: LoginContext,
+    State(pool): State<DbPool>,
+    State(http_client): State<reqwest::Client>,
+    Query(query): Query<AuthRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let code = query.code;
+    let state = query.state;
+    let stored_state = cookies.get(COOKIE_AUTH_CSRF_STATE);
+    let stored_code_verifier = cookies.get(COOKIE_AUTH_CODE_VERIFIER);
+
+    let (Some(csrf_state), Some(code_verifier)) = (stored_state, stored_code_verifier) else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    if csrf_state.value() != state {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let code = AuthorizationCode::new(code);
+    let pkce_code_verifier = PkceCodeVerifier::new(code_verifier.value().to_owned());
+
+    let token_response = provider
+        .client
+        .exchange_code(code)
+        .set_pkce_verifier(pkce_code_verifier)
+        .request_async(|request| oauth_http_client(&http_client, request))
+        .await
+        .context("Failed to get token response")?;
+
+    // Get the user info from the provider
+    let provider_user = provider
+        .provider
+        .get_user(&http_client, token_response.access_token().secret())
+        .await?;
+
+    // Add user session
+    let user = super::find_or_create_user(
+        &pool,
+        &login_context,
+        &cookies,
+        provider.provider.provider(),
+        provider_user.account_id,
+        provider_user.username,
+        provider_user.image_url,
+    )
+    .await
+    .context("Failed to get or create user")?;
+
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, user.id, false, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    // Remove code_verifier and csrf_state cookies
+    let mut remove_csrf_cookie = Cookie::new(COOKIE_AUTH_CSRF_STATE, "");
+    remove_csrf_cookie.set_path("/");
+    remove_csrf_cookie.make_removal();
+
+    let mut remove_code_verifier = Cookie::new(COOKIE_AUTH_CODE_VERIFIER, "");
+    remove_code_verifier.set_path("/");
+    remove_code_verifier.make_removal();
+
+    let cookies = CookieJar::new()
+        .add(remove_csrf_cookie)
+        .add(remove_code_verifier)
+        .add(session_cookie);
+
+    let response = (cookies, redirect).into_response();
+    Ok(response)
+}
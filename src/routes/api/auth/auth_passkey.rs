@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect},
+    routing::post,
+    Json, Router,
+};
+use webauthn_rs::prelude::{
+    DiscoverableAuthentication, DiscoverableKey, PasskeyRegistration, PublicKeyCredential,
+    RegisterPublicKeyCredential, Url, Webauthn, WebauthnBuilder,
+};
+
+use crate::db::DbPool;
+use crate::{
+    constants::{COOKIE_AUTH_PASSKEY_CHALLENGE, PASSKEY_CHALLENGE_DURATION},
+    misc::error::AppError,
+    models::AuthProvider,
+    server::{AppState, CurrentUser, LoginContext, RequireAuth},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+
+pub fn passkey_auth_router(state: &AppState) -> Router<AppState> {
+    // A passkey of a guest would outlive it, guests are deleted when they sign in to an account
+    let register_router = Router::new()
+        .route("/api/auth/passkey/register/start", post(register_start))
+        .route("/api/auth/passkey/register/finish", post(register_finish))
+        .route_layer(RequireAuth::new(state).policy(|x| x.user.provider != AuthProvider::Guest));
+
+    Router::new()
+        .merge(register_router)
+        .route("/api/auth/passkey/login/start", post(login_start))
+        .route("/api/auth/passkey/login/finish", post(login_finish))
+        .route("/api/auth/passkey/:passkey_id/delete", post(delete_passkey))
+}
+
+/// Creates the relying party used to verify passkeys, the `base_url` is used as origin.
+pub fn create_webauthn(base_url: &str) -> Result<Arc<Webauthn>, anyhow::Error> {
+    let origin = Url::parse(base_url).context("Invalid app base url")?;
+    let rp_id = origin
+        .host_str()
+        .context("The app base url should have a host")?
+        .to_owned();
+
+    let webauthn = WebauthnBuilder::new(&rp_id, &origin)
+        .context("Invalid passkey relying party")?
+        .rp_name("Axum OAuth")
+        .build()
+        .context("Failed to create passkey relying party")?;
+
+    Ok(Arc::new(webauthn))
+}
+
+fn challenge_cookie(challenge_id: String) -> Cookie<'static> {
+    let cookie_max_age =
+        cookie::time::Duration::milliseconds(PASSKEY_CHALLENGE_DURATION.as_millis() as i64);
+
+    Cookie::build((COOKIE_AUTH_PASSKEY_CHALLENGE, challenge_id))
+        .http_only(true)
+        .path("/")
+        .same_site(SameSite::Strict)
+        .max_age(cookie_max_age)
+        .into()
+}
+
+fn remove_challenge_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::new(COOKIE_AUTH_PASSKEY_CHALLENGE, "");
+    cookie.set_path("/");
+    cookie.make_removal();
+    cookie
+}
+
+async fn register_start(
+    CurrentUser { user, .. }: CurrentUser,
+    State(pool): State<DbPool>,
+    State(webauthn): State<Arc<Webauthn>>,
+) -> Result<impl IntoResponse, AppError> {
+    // Prevent registering the same authenticator twice
+    let exclude_credentials = crate::db::get_user_passkey_credentials(&pool, user.id)
+        .await
+        .context("Failed to get user passkeys")?
+        .iter()
+        .map(|x| x.cred_id().clone())
+        .collect::<Vec<_>>();
+
+    let (creation_challenge, registration) = webauthn
+        .start_passkey_registration(
+            user.id,
+            &user.username,
+            &user.username,
+            Some(exclude_credentials),
+        )
+        .context("Failed to start passkey registration")?;
+
+    let state = serde_json::to_string(&registration)?;
+    let challenge_id = crate::db::create_passkey_challenge(
+        &pool,
+        Some(user.id),
+        state,
+        PASSKEY_CHALLENGE_DURATION,
+    )
+    .await
+    .context("Failed to create passkey challenge")?;
+
+    let cookies = CookieJar::new().add(challenge_cookie(challenge_id.to_string()));
+    Ok((cookies, Json(creation_challenge)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RegisterRequest {
+    name: Option<String>,
+    credential: RegisterPublicKeyCredential,
+}
+
+async fn register_finish(
+    CurrentUser { user, .. }: CurrentUser,
+    cookies: CookieJar,
+    State(pool): State<DbPool>,
+    State(webauthn): State<Arc<Webauthn>>,
+    Json(request): Json<RegisterRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(challenge_cookie) = cookies.get(COOKIE_AUTH_PASSKEY_CHALLENGE) else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let state =
+        crate::db::consume_passkey_challenge(&pool, challenge_cookie.value(), Some(user.id))
+            .await
+            .context("Failed to get passkey challenge")?;
+
+    let Some(state) = state else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let registration = serde_json::from_str::<PasskeyRegistration>(&state)?;
+    let passkey = match webauthn.finish_passkey_registration(&request.credential, &registration) {
+        Ok(x) => x,
+        Err(err) => {
+            tracing::warn!("passkey registration failed for user '{}': {err}", user.id);
+            return Ok(StatusCode::BAD_REQUEST.into_response());
+        }
+    };
+
+    let name = request
+        .name
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "Passkey".to_owned());
+
+    let user_passkey = crate::db::create_user_passkey(&pool, user.id, name, &passkey)
+        .await
+        .context("Failed to save passkey")?;
+
+    let cookies = CookieJar::new().add(remove_challenge_cookie());
+    Ok((cookies, Json(user_passkey)).into_response())
+}
+
+async fn login_start(
+    State(pool): State<DbPool>,
+    State(webauthn): State<Arc<Webauthn>>,
+    Query(query): Query<super::RememberMeQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    // The user is not known yet, the authenticator will tell us which passkey was used
+    let (request_challenge, authentication) = webauthn
+        .start_discoverable_authentication()
+        .context("Failed to start passkey authentication")?;
+
+    let state = serde_json::to_string(&authentication)?;
+    let challenge_id =
+        crate::db::create_passkey_challenge(&pool, None, state, PASSKEY_CHALLENGE_DURATION)
+            .await
+            .context("Failed to create passkey challenge")?;
+
+    let max_age =
+        cookie::time::Duration::milliseconds(PASSKEY_CHALLENGE_DURATION.as_millis() as i64);
+    let cookies = CookieJar::new()
+        .add(challenge_cookie(challenge_id.to_string()))
+        .add(super::remember_me_cookie(query.remember_me, max_age));
+
+    Ok((cookies, Json(request_challenge)))
+}
+
+async fn login_finish(
+    cookies: CookieJar,
+    login_context: LoginContext,
+    State(pool): State<DbPool>,
+    State(webauthn): State<Arc<Webauthn>>,
+    Json(credential): Json<PublicKeyCredential>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(challenge_cookie) = cookies.get(COOKIE_AUTH_PASSKEY_CHALLENGE) else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let state = crate::db::consume_passkey_challenge(&pool, challenge_cookie.value(), None)
+        .await
+        .context("Failed to get passkey challenge")?;
+
+    let Some(state) = state else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let authentication = serde_json::from_str::<DiscoverableAuthentication>(&state)?;
+    let Ok((user_id, credential_id)) = webauthn.identify_discoverable_authentication(&credential)
+    else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+
+    let passkey = crate::db::get_passkey_by_credential_id(&pool, &hex::encode(credential_id))
+        .await
+        .context("Failed to get passkey")?;
+
+    // The passkey must exist and belong to the user the authenticator claims
+    let mut passkey = match passkey {
+        Some((owner_id, passkey)) if owner_id == user_id => passkey,
+        _ => return Ok(StatusCode::UNAUTHORIZED.into_response()),
+    };
+
+    let result = match webauthn.finish_discoverable_authentication(
+        &credential,
+        authentication,
+        &[DiscoverableKey::from(&passkey)],
+    ) {
+        Ok(x) => x,
+        Err(err) => {
+            tracing::warn!("passkey authentication failed for user '{user_id}': {err}");
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+    };
+
+    // Persist the updated signature counter and the last usage
+    passkey.update_credential(&result);
+    crate::db::update_passkey_credential(&pool, &passkey)
+        .await
+        .context("Failed to update passkey")?;
+
+    super::merge_current_guest(&pool, &login_context, &cookies, user_id)
+        .await
+        .context("Failed to merge guest user")?;
+
+    // Passkeys require user verification, so they already count as a second factor
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, user_id, true, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    let cookies = CookieJar::new()
+        .add(remove_challenge_cookie())
+        .add(session_cookie);
+
+    // The page follows the redirect itself, e.g. to the login page if the user is disabled
+    let redirect = redirect.into_response();
+    let redirect_to = redirect
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or("/");
+
+    let body = serde_json::json!({ "redirect_to": redirect_to });
+    Ok((cookies, Json(body)).into_response())
+}
+
+async fn delete_passkey(
+    CurrentUser { user, .. }: CurrentUser,
+    State(pool): State<DbPool>,
+    Path(passkey_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let deleted = crate::db::delete_user_passkey(&pool, user.id, &passkey_id)
+        .await
+        .context("Failed to delete passkey")?;
+
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    Ok(Redirect::to("/").into_response())
+}
@@ -0,0 +1,78 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    routing::post,
+    Router,
+};
+
+use crate::{
+    constants::COOKIE_AUTH_SESSION,
+    misc::error::AppError,
+    server::{AppState, CurrentUser, SessionCache},
+    session_store::SharedSessionStore,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
+
+pub fn sessions_auth_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/auth/sessions/:handle/delete", post(delete_session))
+        .route(
+            "/api/auth/sessions/delete-others",
+            post(delete_other_sessions),
+        )
+}
+
+async fn delete_session(
+    CurrentUser { user, session, .. }: CurrentUser,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+    Path(handle): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let sessions = session_store
+        .list_for_user(user.id, Some(&session.token_hash))
+        .await
+        .context("Failed to get user sessions")?;
+
+    let Some(session) = sessions.into_iter().find(|x| x.handle == handle) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    session_store
+        .delete_by_handle(user.id, handle)
+        .await
+        .context("Failed to delete user session")?;
+
+    // Only the handle of the session is known
+    session_cache.invalidate_user(user.id);
+    tracing::info!("user '{}' signed out a session", user.id);
+
+    // Signing out the current device is the same as logging out
+    if session.current {
+        let mut remove_session_cookie = Cookie::new(COOKIE_AUTH_SESSION, "");
+        remove_session_cookie.set_path("/");
+        remove_session_cookie.make_removal();
+
+        let cookies = CookieJar::new().add(remove_session_cookie);
+        return Ok((cookies, Redirect::to("/login")).into_response());
+    }
+
+    Ok(Redirect::to("/settings/sessions").into_response())
+}
+
+async fn delete_other_sessions(
+    CurrentUser { user, session, .. }: CurrentUser,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
+) -> Result<impl IntoResponse, AppError> {
+    session_store
+        .delete_for_user(user.id, Some(&session.token_hash))
+        .await
+        .context("Failed to delete user sessions")?;
+
+    session_cache.invalidate_user(user.id);
+
+    tracing::info!("user '{}' signed out all the other sessions", user.id);
+    Ok(Redirect::to("/settings/sessions"))
+}
@@ -0,0 +1,295 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    routing::post,
+    Form, Router,
+};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::{
+    constants::{COOKIE_AUTH_SESSION, RECOVERY_CODE_COUNT, SECOND_FACTOR_MAX_ATTEMPTS},
+    misc::{
+        error::AppError,
+        token::{generate_recovery_code, hash_token, normalize_recovery_code},
+        totp,
+    },
+    routes::pages::RecoveryCodesTemplate,
+    server::{
+        AppState, CurrentUser, LoginContext, PendingSecondFactorUser, SessionConfig, UserTheme,
+    },
+    session_store::SharedSessionStore,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
+
+pub fn totp_auth_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/auth/totp/setup", post(setup))
+        .route("/api/auth/totp/enable", post(enable))
+        .route("/api/auth/totp/disable", post(disable))
+        .route("/api/auth/totp/verify", post(verify))
+        .route(
+            "/api/auth/totp/recovery-codes",
+            post(regenerate_recovery_codes),
+        )
+        .route("/api/auth/recovery/login", post(recovery_login))
+}
+
+async fn create_recovery_codes(pool: &DbPool, user_id: Uuid) -> Result<Vec<String>, anyhow::Error> {
+    let codes = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect::<Vec<_>>();
+
+    let code_hashes = codes
+        .iter()
+        .map(|x| hash_token(&normalize_recovery_code(x)))
+        .collect::<Vec<_>>();
+
+    crate::db::replace_recovery_codes(pool, user_id, &code_hashes).await?;
+    Ok(codes)
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CodeRequest {
+    code: String,
+}
+
+/// Creates a new secret to enable the second factor with, replacing the unconfirmed one.
+async fn setup(
+    CurrentUser { user, .. }: CurrentUser,
+    State(pool): State<DbPool>,
+) -> Result<impl IntoResponse, AppError> {
+    let secret = totp::generate_secret();
+
+    // Never replaces a confirmed secret
+    crate::db::create_unconfirmed_user_totp(&pool, user.id, &secret)
+        .await
+        .context("Failed to create user TOTP")?;
+
+    Ok(Redirect::to("/settings/2fa"))
+}
+
+async fn enable(
+    CurrentUser { user, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(pool): State<DbPool>,
+    Form(request): Form<CodeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_totp = crate::db::get_user_totp(&pool, user.id)
+        .await
+        .context("Failed to get user TOTP")?;
+
+    let Some(user_totp) = user_totp.filter(|x| x.confirmed_at.is_none()) else {
+        return Ok(Redirect::to("/settings/2fa").into_response());
+    };
+
+    let totp = totp::build_totp(&user_totp.secret, &user.username)?;
+    let Some(step) = totp::verify_code(&totp, &request.code, user_totp.last_used_step) else {
+        return Ok(Redirect::to("/settings/2fa?error=true").into_response());
+    };
+
+    crate::db::confirm_user_totp(&pool, user.id, step)
+        .await
+        .context("Failed to confirm user TOTP")?;
+
+    let codes = create_recovery_codes(&pool, user.id)
+        .await
+        .context("Failed to create recovery codes")?;
+
+    tracing::info!("user '{}' enabled two-factor authentication", user.id);
+    Ok(RecoveryCodesTemplate {
+        theme: theme.unwrap_or_default(),
+        user: Some(user),
+        codes,
+    }
+    .into_response())
+}
+
+async fn disable(
+    CurrentUser { user, .. }: CurrentUser,
+    State(pool): State<DbPool>,
+    Form(request): Form<CodeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_totp = crate::db::get_user_totp(&pool, user.id)
+        .await
+        .context("Failed to get user TOTP")?;
+
+    let Some(user_totp) = user_totp.filter(|x| x.confirmed_at.is_some()) else {
+        return Ok(Redirect::to("/settings/2fa"));
+    };
+
+    // Require a valid code to prevent disabling it from an unattended session
+    let totp = totp::build_totp(&user_totp.secret, &user.username)?;
+    if totp::verify_code(&totp, &request.code, user_totp.last_used_step).is_none() {
+        return Ok(Redirect::to("/settings/2fa?error=true"));
+    }
+
+    crate::db::delete_user_totp(&pool, user.id)
+        .await
+        .context("Failed to delete user TOTP")?;
+
+    crate::db::delete_recovery_codes(&pool, user.id)
+        .await
+        .context("Failed to delete recovery codes")?;
+
+    tracing::info!("user '{}' disabled two-factor authentication", user.id);
+    Ok(Redirect::to("/settings/2fa"))
+}
+
+async fn regenerate_recovery_codes(
+    CurrentUser { user, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(pool): State<DbPool>,
+    Form(request): Form<CodeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_totp = crate::db::get_user_totp(&pool, user.id)
+        .await
+        .context("Failed to get user TOTP")?;
+
+    let Some(user_totp) = user_totp.filter(|x| x.confirmed_at.is_some()) else {
+        return Ok(Redirect::to("/settings/2fa").into_response());
+    };
+
+    let totp = totp::build_totp(&user_totp.secret, &user.username)?;
+    if totp::verify_code(&totp, &request.code, user_totp.last_used_step).is_none() {
+        return Ok(Redirect::to("/settings/2fa?error=true").into_response());
+    }
+
+    let codes = create_recovery_codes(&pool, user.id)
+        .await
+        .context("Failed to create recovery codes")?;
+
+    tracing::info!("user '{}' regenerated the recovery codes", user.id);
+    Ok(RecoveryCodesTemplate {
+        theme: theme.unwrap_or_default(),
+        user: Some(user),
+        codes,
+    }
+    .into_response())
+}
+
+async fn verify(
+    PendingSecondFactorUser(user): PendingSecondFactorUser,
+    cookies: CookieJar,
+    State(pool): State<DbPool>,
+    State(session_config): State<SessionConfig>,
+    State(session_store): State<SharedSessionStore>,
+    Form(request): Form<CodeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(session_cookie) = cookies.get(COOKIE_AUTH_SESSION) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let session_token = session_cookie.value().to_owned();
+    let token_hash = hash_token(&session_token);
+    let user_totp = crate::db::get_user_totp(&pool, user.id)
+        .await
+        .context("Failed to get user TOTP")?
+        .filter(|x| x.confirmed_at.is_some())
+        .context("Pending session for an user without second factor")?;
+
+    let totp = totp::build_totp(&user_totp.secret, &user.username)?;
+    let step = totp::verify_code(&totp, &request.code, user_totp.last_used_step);
+
+    // The step is only accepted once, this prevents replaying a code
+    let verified = match step {
+        Some(step) => crate::db::update_totp_last_used_step(&pool, user.id, step)
+            .await
+            .context("Failed to update user TOTP")?,
+        None => {
+            // Otherwise it may be one of the recovery codes
+            let code_hash = hash_token(&normalize_recovery_code(&request.code));
+            let used = crate::db::use_recovery_code(&pool, user.id, &code_hash)
+                .await
+                .context("Failed to use recovery code")?;
+
+            if used {
+                tracing::info!("user '{}' used a recovery code", user.id);
+            }
+
+            used
+        }
+    };
+
+    if !verified {
+        let attempts = session_store
+            .record_failed_second_factor(&token_hash)
+            .await
+            .context("Failed to record second factor attempt")?;
+
+        if attempts < SECOND_FACTOR_MAX_ATTEMPTS {
+            return Ok(Redirect::to("/2fa?error=true").into_response());
+        }
+
+        // Too many attempts, the user needs to sign in again
+        tracing::warn!("too many second factor attempts for user '{}'", user.id);
+        session_store
+            .delete(&token_hash)
+            .await
+            .context("Failed to delete user session")?;
+
+        let mut remove_session_cookie = Cookie::new(COOKIE_AUTH_SESSION, "");
+        remove_session_cookie.set_path("/");
+        remove_session_cookie.make_removal();
+
+        let cookies = CookieJar::new().add(remove_session_cookie);
+        return Ok((cookies, Redirect::to("/login")).into_response());
+    }
+
+    // The session keeps the "keep me signed in" choice made before the second factor
+    let persistent = session_store
+        .get(&token_hash)
+        .await
+        .context("Failed to get user session")?
+        .is_some_and(|x| x.persistent);
+
+    let session_duration = session_config.session_timeout(persistent);
+    session_store
+        .complete_second_factor(&token_hash, session_duration)
+        .await
+        .context("Failed to complete user session")?;
+
+    let max_age = persistent.then_some(session_duration);
+    let cookies = CookieJar::new().add(crate::server::session_cookie(session_token, max_age));
+    Ok((cookies, Redirect::to("/")).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RecoveryLoginRequest {
+    code: String,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+async fn recovery_login(
+    cookies: CookieJar,
+    mut login_context: LoginContext,
+    State(pool): State<DbPool>,
+    Form(request): Form<RecoveryLoginRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let code_hash = hash_token(&normalize_recovery_code(&request.code));
+    let user_id = crate::db::use_recovery_code_for_login(&pool, &code_hash)
+        .await
+        .context("Failed to use recovery code")?;
+
+    let Some(user_id) = user_id else {
+        return Ok(Redirect::to("/login?recovery_error=true").into_response());
+    };
+
+    tracing::info!("user '{user_id}' signed in with a recovery code");
+
+    super::merge_current_guest(&pool, &login_context, &cookies, user_id)
+        .await
+        .context("Failed to merge guest user")?;
+
+    // The recovery code replaces both the provider and the second factor
+    login_context.remember_me = request.remember_me;
+    let (session_cookie, redirect) =
+        super::create_login_session(&pool, user_id, true, &login_context)
+            .await
+            .context("Failed to create user session")?;
+
+    let cookies = CookieJar::new().add(session_cookie);
+    Ok((cookies, redirect).into_response())
+}
@@ -1,54 +1,60 @@
 use self::{
-    auth_discord::discord_auth_router, auth_github::github_auth_router,
-    auth_google::google_auth_router,
+    auth_email::email_auth_router, auth_guest::guest_auth_router, auth_local::local_auth_router,
+    auth_oauth::oauth_auth_router, auth_passkey::passkey_auth_router,
+    auth_sessions::sessions_auth_router, auth_totp::totp_auth_router,
+};
+use crate::db::DbPool;
+use crate::{
+    constants::{COOKIE_AUTH_REMEMBER_ME, COOKIE_AUTH_SESSION, SECOND_FACTOR_PENDING_DURATION},
+    misc::token::{generate_token, hash_token},
+    models::{AuthProvider, User},
+    server::{session_cookie, Admin, AppState, CurrentUser, LoginContext, RoleName, SessionCache},
+    session_store::{NewUserSession, SharedSessionStore},
 };
-use crate::constants::COOKIE_AUTH_SESSION;
 use axum::{
+    extract::State,
     http::StatusCode,
     response::{ErrorResponse, IntoResponse, Redirect},
     routing::get,
-    Extension, Json, Router,
+    Json, Router,
 };
-use axum_extra::extract::cookie::CookieJar;
+use axum_extra::extract::cookie::{CookieJar, SameSite};
 use cookie::Cookie;
-use sqlx::SqlitePool;
+use uuid::Uuid;
+
+mod auth_email;
+mod auth_guest;
+mod auth_local;
+mod auth_oauth;
+mod auth_passkey;
+mod auth_sessions;
+mod auth_totp;
 
-mod auth_discord;
-mod auth_github;
-mod auth_google;
+pub use auth_local::LocalAuthConfig;
+pub use auth_passkey::create_webauthn;
 
-pub fn auth_router() -> Router {
+pub fn auth_router(state: &AppState) -> Router<AppState> {
     Router::new()
         .route("/api/auth/me", get(me))
         .route("/api/auth/logout", get(logout))
-        .merge(google_auth_router())
-        .merge(github_auth_router())
-        .merge(discord_auth_router())
+        .merge(oauth_auth_router(state))
+        .merge(email_auth_router())
+        .merge(passkey_auth_router(state))
+        .merge(local_auth_router())
+        .merge(guest_auth_router())
+        .merge(totp_auth_router())
+        .merge(sessions_auth_router())
 }
 
-pub async fn me(
-    cookies: CookieJar,
-    Extension(pool): Extension<SqlitePool>,
-) -> Result<impl IntoResponse, ErrorResponse> {
-    let session_cookie = cookies.get(COOKIE_AUTH_SESSION);
-
-    let Some(session_cookie) = session_cookie else {
-        return Err(ErrorResponse::from(StatusCode::UNAUTHORIZED));
-    };
-
-    let user = crate::db::get_user_by_session_id(&pool, session_cookie.value())
-        .await
-        .map_err(|_| ErrorResponse::from(StatusCode::INTERNAL_SERVER_ERROR))?;
-
-    match user {
-        Some(user) => Ok(Json(user).into_response()),
-        None => Err(ErrorResponse::from(StatusCode::NOT_FOUND)),
-    }
+/// Returns the current user, expired sessions are rejected and their cookie is removed.
+pub async fn me(current_user: CurrentUser) -> Json<CurrentUser> {
+    Json(current_user)
 }
 
 pub async fn logout(
     mut cookies: CookieJar,
-    Extension(pool): Extension<SqlitePool>,
+    State(session_cache): State<SessionCache>,
+    State(session_store): State<SharedSessionStore>,
 ) -> Result<impl IntoResponse, ErrorResponse> {
     let session_cookie = cookies.get(COOKIE_AUTH_SESSION);
 
@@ -56,10 +62,14 @@ pub async fn logout(
         return Err(ErrorResponse::from(StatusCode::UNAUTHORIZED));
     };
 
-    crate::db::delete_user_session(&pool, session_cookie.value())
+    let token_hash = hash_token(session_cookie.value());
+    session_store
+        .delete(&token_hash)
         .await
         .map_err(|_| ErrorResponse::from(StatusCode::INTERNAL_SERVER_ERROR))?;
 
+    session_cache.invalidate(&token_hash);
+
     let mut remove_session_cookie = Cookie::new(COOKIE_AUTH_SESSION, "");
     remove_session_cookie.set_path("/");
     remove_session_cookie.make_removal();
@@ -67,3 +77,208 @@ pub async fn logout(
     cookies = cookies.add(remove_session_cookie);
     Ok((cookies, Redirect::to("/")))
 }
+
+/// Creates the session for an user that just signed in, returns the session cookie and where to redirect.
+///
+/// Users with a second factor get a short lived pending session until they verify it on `/2fa`,
+/// unless the sign in method already verified it. Disabled users are sent back to the login page.
+pub(crate) async fn create_login_session(
+    pool: &DbPool,
+    user_id: Uuid,
+    second_factor_verified: bool,
+    login_context: &LoginContext,
+) -> Result<(Cookie<'static>, Redirect), anyhow::Error> {
+    if !crate::db::record_user_login(pool, user_id).await? {
+        tracing::warn!("disabled user '{user_id}' tried to sign in");
+
+        let mut remove_session_cookie = Cookie::new(COOKIE_AUTH_SESSION, "");
+        remove_session_cookie.set_path("/");
+        remove_session_cookie.make_removal();
+        return Ok((remove_session_cookie, Redirect::to("/login?disabled=true")));
+    }
+
+    let second_factor_pending =
+        !second_factor_verified && crate::db::has_second_factor(pool, user_id).await?;
+
+    // The session is extended while it is used, see `CurrentUser`
+    let persistent = login_context.remember_me;
+    let session_duration = if second_factor_pending {
+        SECOND_FACTOR_PENDING_DURATION
+    } else {
+        login_context.session_config.session_timeout(persistent)
+    };
+
+    // Only the hash is stored, so a leaked database doesn't give access to the sessions
+    let session_token = generate_token();
+    login_context
+        .session_store
+        .create(NewUserSession {
+            token_hash: &hash_token(&session_token),
+            user_id,
+            duration: session_duration,
+            second_factor_pending,
+            persistent,
+            ip_address: login_context.client.ip_address.as_deref(),
+            user_agent: login_context.client.user_agent.as_deref(),
+        })
+        .await?;
+
+    // Otherwise the cookie only lasts until the browser is closed
+    let max_age = persistent.then_some(session_duration);
+    let cookie = session_cookie(session_token, max_age);
+    let redirect = if second_factor_pending {
+        Redirect::to("/2fa")
+    } else {
+        Redirect::to("/")
+    };
+
+    Ok((cookie, redirect))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct RememberMeQuery {
+    #[serde(default)]
+    remember_me: bool,
+}
+
+/// Keeps the "keep me signed in" choice of the user until the sign in flow that is starting finishes.
+pub(crate) fn remember_me_cookie(
+    remember_me: bool,
+    max_age: cookie::time::Duration,
+) -> Cookie<'static> {
+    let mut cookie: Cookie = Cookie::build((COOKIE_AUTH_REMEMBER_ME, "true"))
+        .http_only(true)
+        .path("/")
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .into();
+
+    if !remember_me {
+        cookie.make_removal();
+    }
+
+    cookie
+}
+
+/// Gives the admin role to a new user if its account is in `ADMIN_ACCOUNTS`, so a role revoked later
+/// is not given back when the user signs in again.
+pub(crate) async fn bootstrap_admin(
+    pool: &DbPool,
+    admin_accounts: &[(AuthProvider, String)],
+    user: &User,
+) -> Result<(), anyhow::Error> {
+    let is_admin_account = admin_accounts
+        .iter()
+        .any(|(provider, account_id)| *provider == user.provider && *account_id == user.account_id);
+
+    // The user is new, so it has no cached sessions yet
+    if is_admin_account && crate::db::grant_user_role(pool, user.id, Admin::NAME).await? {
+        tracing::info!(
+            "{} account '{}' was given the admin role",
+            user.provider,
+            user.account_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the guest of the current session, if any.
+async fn get_current_guest(
+    pool: &DbPool,
+    login_context: &LoginContext,
+    cookies: &CookieJar,
+) -> Result<Option<User>, anyhow::Error> {
+    let Some(session_cookie) = cookies.get(COOKIE_AUTH_SESSION) else {
+        return Ok(None);
+    };
+
+    // An invalid or stale cookie is not an error here, the user is signing in again
+    let token_hash = hash_token(session_cookie.value());
+    let session_store = &*login_context.session_store;
+    let user = crate::session_store::get_session_user(session_store, pool, &token_hash, false)
+        .await
+        .unwrap_or_default();
+
+    Ok(user
+        .map(|(user, _)| user)
+        .filter(|x| x.provider == AuthProvider::Guest))
+}
+
+/// Gets or creates the user of a provider account.
+///
+/// When signing in from a guest session the guest becomes the new user,
+/// or is merged into the user if the account already exists.
+pub(crate) async fn find_or_create_user(
+    pool: &DbPool,
+    login_context: &LoginContext,
+    cookies: &CookieJar,
+    provider: AuthProvider,
+    account_id: String,
+    username: String,
+    image_url: Option<String>,
+) -> Result<User, anyhow::Error> {
+    let existing_user =
+        crate::db::get_user_by_account_id(pool, provider, account_id.clone()).await?;
+    let guest = get_current_guest(pool, login_context, cookies).await?;
+
+    let user = match (existing_user, guest) {
+        (Some(user), Some(guest)) => {
+            delete_guest_sessions(login_context, guest.id).await?;
+            crate::db::merge_guest_user(pool, guest.id, user.id).await?;
+            tracing::info!("guest '{}' was merged into user '{}'", guest.id, user.id);
+            user
+        }
+        (Some(user), None) => user,
+        (None, Some(guest)) => {
+            delete_guest_sessions(login_context, guest.id).await?;
+            let user = crate::db::upgrade_guest_user(
+                pool, guest.id, account_id, provider, username, image_url,
+            )
+            .await?;
+            tracing::info!("guest '{}' was upgraded to a {provider} user", guest.id);
+            bootstrap_admin(pool, &login_context.config.admin_accounts, &user).await?;
+            user
+        }
+        (None, None) => {
+            let user =
+                crate::db::create_user(pool, account_id, provider, username, image_url).await?;
+            bootstrap_admin(pool, &login_context.config.admin_accounts, &user).await?;
+            user
+        }
+    };
+
+    Ok(user)
+}
+
+/// Merges the guest of the current session, if any, into an user that just signed in.
+pub(crate) async fn merge_current_guest(
+    pool: &DbPool,
+    login_context: &LoginContext,
+    cookies: &CookieJar,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    if let Some(guest) = get_current_guest(pool, login_context, cookies).await? {
+        if guest.id != user_id {
+            delete_guest_sessions(login_context, guest.id).await?;
+            crate::db::merge_guest_user(pool, guest.id, user_id).await?;
+            tracing::info!("guest '{}' was merged into user '{user_id}'", guest.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Signs out a guest that is being merged or upgraded, the user gets a new session.
+async fn delete_guest_sessions(
+    login_context: &LoginContext,
+    guest_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    login_context
+        .session_store
+        .delete_for_user(guest_id, None)
+        .await?;
+
+    login_context.session_cache.invalidate_user(guest_id);
+    Ok(())
+}
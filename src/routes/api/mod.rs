@@ -1,5 +1,8 @@
+mod admin;
 mod auth;
 
+pub use auth::{create_webauthn, LocalAuthConfig};
+
 use askama_axum::IntoResponse;
 use axum::{
     http::{header, HeaderMap},
@@ -10,11 +13,16 @@ use axum::{
 use axum_extra::extract::CookieJar;
 use cookie::Cookie;
 
-use crate::{constants::COOKIE_THEME, misc::Theme, server::UserTheme};
+use crate::{
+    constants::COOKIE_THEME,
+    misc::Theme,
+    server::{AppState, UserTheme},
+};
 
-pub fn api_router() -> Router {
+pub fn api_router(state: &AppState) -> Router<AppState> {
     Router::new()
-        .merge(auth::auth_router())
+        .merge(auth::auth_router(state))
+        .merge(admin::admin_router(state))
         .route("/api/toggle_theme", post(toggle_theme))
 }
 
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{middleware, Router};
+
+use crate::{
+    config::AuthConfig,
+    db::DbPool,
+    mailer::SharedMailer,
+    providers::{OAuthProvider, ProviderClient},
+    server::{AppState, RateLimiter, SessionCache},
+    session_store::{DatabaseSessionStore, SharedSessionStore},
+};
+
+/// The routes of the sign in flows, the account pages and the administration, ready to be merged
+/// into the router of an application.
+///
+/// ```ignore
+/// let auth_router = AuthRouter::builder()
+///     .config(AuthConfig::new("https://example.com"))
+///     .pool(pool)
+///     .provider(GithubProvider::new(github_credentials))
+///     .store(Arc::new(MemorySessionStore::default()))
+///     .build()?;
+///
+/// let app = Router::new().route("/hello", get(hello)).merge(auth_router);
+/// ```
+///
+/// The routes of the application can use the [`CurrentUser`](crate::CurrentUser) extractor when
+/// their state is the [`AppState`] of [`AuthRouterBuilder::build_state`], the session cookie is only
+/// renewed in the routes that are also wrapped with [`session_cookie_middleware`](crate::session_cookie_middleware).
+pub struct AuthRouter;
+
+impl AuthRouter {
+    pub fn builder() -> AuthRouterBuilder {
+        AuthRouterBuilder::default()
+    }
+
+    /// Returns the routes using an already built state.
+    pub fn router(state: &AppState) -> Router {
+        Router::new()
+            .merge(super::api_router(state))
+            .merge(super::pages_router(state))
+            .with_state(state.clone())
+            .layer(middleware::from_fn(super::error_handler_middleware))
+            .layer(middleware::from_fn(
+                crate::server::session_cookie_middleware,
+            ))
+    }
+}
+
+#[derive(Default)]
+pub struct AuthRouterBuilder {
+    config: Option<AuthConfig>,
+    pool: Option<DbPool>,
+    providers: Vec<Arc<dyn OAuthProvider>>,
+    store: Option<SharedSessionStore>,
+    mailer: Option<SharedMailer>,
+    http_client: Option<reqwest::Client>,
+}
+
+impl AuthRouterBuilder {
+    /// The settings of the routes, this is required.
+    pub fn config(mut self, config: AuthConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// The database with the users, this is required.
+    pub fn pool(mut self, pool: DbPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Adds a provider to sign in with, the login buttons are displayed in the order they are added.
+    pub fn provider(mut self, provider: impl OAuthProvider) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Where the sessions are kept, defaults to the database.
+    pub fn store(mut self, store: SharedSessionStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// How the sign in emails are sent, defaults to the mailer in the config.
+    /// The email sign-in is disabled when there is none.
+    pub fn mailer(mut self, mailer: SharedMailer) -> Self {
+        self.mailer = Some(mailer);
+        self
+    }
+
+    /// The client of the requests to the providers, defaults to one with timeouts that doesn't follow redirects.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Returns the state of the routes, for applications that also use it in their own routes.
+    pub fn build_state(self) -> Result<AppState, anyhow::Error> {
+        let config = self.config.context("The auth router needs a config")?;
+        let pool = self.pool.context("The auth router needs a database pool")?;
+
+        let mailer = match self.mailer {
+            Some(mailer) => Some(mailer),
+            None => config
+                .mailer
+                .as_ref()
+                .map(crate::mailer::create_mailer)
+                .transpose()
+                .context("Failed to create mailer")?,
+        };
+
+        let webauthn = super::create_webauthn(&config.base_url)
+            .context("Failed to create passkey verifier")?;
+
+        let session_store = self
+            .store
+            .unwrap_or_else(|| Arc::new(DatabaseSessionStore::new(pool.clone())));
+
+        for (i, provider) in self.providers.iter().enumerate() {
+            let name = provider.provider();
+            if self.providers[..i].iter().any(|x| x.provider() == name) {
+                anyhow::bail!("The {name} provider was added more than once");
+            }
+        }
+
+        let providers = self
+            .providers
+            .into_iter()
+            .map(|provider| {
+                let name = provider.provider();
+                ProviderClient::new(provider, &config.base_url)
+                    .map(Arc::new)
+                    .with_context(|| format!("Failed to create {name} auth client"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => {
+                crate::misc::http::create_http_client().context("Failed to create http client")?
+            }
+        };
+
+        Ok(AppState {
+            pool,
+            session_cache: SessionCache::from_config(&config.session_cache),
+            config: Arc::new(config),
+            mailer,
+            webauthn,
+            session_store,
+            providers: Arc::new(providers),
+            rate_limiter: RateLimiter::default(),
+            http_client,
+        })
+    }
+
+    pub fn build(self) -> Result<Router, anyhow::Error> {
+        let state = self.build_state()?;
+        Ok(AuthRouter::router(&state))
+    }
+}
@@ -1,6 +1,11 @@
 mod api;
+mod auth_router;
 mod pages;
 
 pub use api::api_router;
+pub use api::{create_webauthn, LocalAuthConfig};
+pub use auth_router::{AuthRouter, AuthRouterBuilder};
 pub use pages::pages_router;
-pub use pages::error_handler_middleware;
\ No newline at end of file
+#[cfg(feature = "mock")]
+pub(crate) use pages::filters;
+pub use pages::{error_handler_middleware, not_found};
\ No newline at end of file
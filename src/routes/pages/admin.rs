@@ -0,0 +1,120 @@
+use crate::db::DbPool;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use uuid::Uuid;
+
+use super::filters;
+use crate::{
+    constants::ADMIN_USERS_PAGE_SIZE,
+    misc::{error::AppError, Theme},
+    models::{Permission, Role, User, UserSessionInfo, UserSummary},
+    server::{Admin, AppState, CurrentUser, RequireAuth, RoleName, UserTheme},
+    session_store::SharedSessionStore,
+};
+
+pub fn admin_pages_router(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin", get(users))
+        .route("/admin/users/:user_id", get(user_details))
+        .route_layer(
+            RequireAuth::new(state)
+                .role(Admin::NAME)
+                .permission(Permission::ReadUsers),
+        )
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct UsersQuery {
+    q: Option<String>,
+    page: Option<i64>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_users.html")]
+struct AdminUsersTemplate {
+    theme: Theme,
+    user: Option<User>,
+    users: Vec<UserSummary>,
+    search: String,
+    page: i64,
+    total_pages: i64,
+    total_users: i64,
+}
+
+async fn users(
+    CurrentUser { user, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(pool): State<DbPool>,
+    Query(query): Query<UsersQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let theme = theme.unwrap_or_default();
+    let search = query.q.unwrap_or_default().trim().to_owned();
+    let page = query.page.unwrap_or(1).max(1);
+    let Some(offset) = (page - 1).checked_mul(ADMIN_USERS_PAGE_SIZE) else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let (users, total_users) = crate::db::search_users(
+        &pool,
+        Some(search.as_str()).filter(|x| !x.is_empty()),
+        ADMIN_USERS_PAGE_SIZE,
+        offset,
+    )
+    .await?;
+
+    let total_pages = (total_users + ADMIN_USERS_PAGE_SIZE - 1) / ADMIN_USERS_PAGE_SIZE;
+
+    Ok(AdminUsersTemplate {
+        theme,
+        user: Some(user),
+        users,
+        search,
+        page,
+        total_pages: total_pages.max(1),
+        total_users,
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "admin_user.html")]
+struct AdminUserTemplate {
+    theme: Theme,
+    user: Option<User>,
+    details: UserSummary,
+    roles: Vec<Role>,
+    sessions: Vec<UserSessionInfo>,
+    is_current_user: bool,
+}
+
+async fn user_details(
+    CurrentUser { user, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(pool): State<DbPool>,
+    State(session_store): State<SharedSessionStore>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let theme = theme.unwrap_or_default();
+    let Some(details) = crate::db::get_user_summary(&pool, user_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let roles = crate::db::get_user_roles(&pool, user_id).await?;
+    let sessions = session_store.list_for_user(user_id, None).await?;
+
+    Ok(AdminUserTemplate {
+        theme,
+        is_current_user: user.id == details.id,
+        user: Some(user),
+        details,
+        roles,
+        sessions,
+    }
+    .into_response())
+}
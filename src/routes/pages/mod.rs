@@ -1,21 +1,57 @@
+use crate::db::DbPool;
 use crate::{
-    misc::{PageError, Theme},
-    models::User,
-    server::{CurrentUser, UserTheme},
+    constants::RECOVERY_CODE_LOW_THRESHOLD,
+    mailer::SharedMailer,
+    misc::{error::AppError, totp, PageError, Theme},
+    models::{AuthProvider, User, UserPasskey, UserSessionInfo},
+    providers::{LoginButton, SharedProviders},
+    routes::LocalAuthConfig,
+    server::{
+        Admin, AppState, CurrentUser, PendingSecondFactorUser, RequireAuth, RoleName, UserTheme,
+    },
+    session_store::SharedSessionStore,
 };
 use askama::Template;
 use askama_axum::IntoResponse;
 use axum::{
-    extract::Request, http::StatusCode, middleware, middleware::Next, response::Redirect,
-    routing::get, Router,
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware,
+    middleware::Next,
+    response::Redirect,
+    routing::get,
+    Router,
 };
 
-pub fn pages_router() -> Router {
-    Router::new()
+mod admin;
+
+pub fn pages_router(state: &AppState) -> Router<AppState> {
+    // Guests don't have credentials to protect
+    let account_settings = Router::new()
+        .route("/settings/2fa", get(two_factor_settings))
+        .route_layer(RequireAuth::new(state).policy(|x| x.user.provider != AuthProvider::Guest));
+
+    let signed_in_pages = Router::new()
         .route("/", get(home))
+        .route("/settings/password", get(password_settings))
+        .route("/settings/sessions", get(sessions_settings))
+        .merge(account_settings)
+        .route_layer(RequireAuth::new(state).redirect_to("/login"));
+
+    let sign_in_pages = Router::new()
         .route("/login", get(login))
-        .layer(middleware::from_fn(auth_middleware))
-        .fallback(not_found)
+        .route("/register", get(register))
+        .route("/login/email", get(email_login))
+        .route("/2fa", get(two_factor))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            sign_in_middleware,
+        ));
+
+    Router::new()
+        .merge(signed_in_pages)
+        .merge(sign_in_pages)
+        .merge(admin::admin_pages_router(state))
 }
 
 #[derive(Template)]
@@ -23,14 +59,43 @@ pub fn pages_router() -> Router {
 struct HomeTemplate {
     theme: Theme,
     user: Option<User>,
+    passkeys: Vec<UserPasskey>,
+    recovery_codes_remaining: Option<i64>,
+    has_password: bool,
+    is_guest: bool,
+    is_admin: bool,
 }
 
-async fn home(CurrentUser(user): CurrentUser, UserTheme(theme): UserTheme) -> HomeTemplate {
+async fn home(
+    current_user: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(pool): State<DbPool>,
+) -> Result<HomeTemplate, AppError> {
     let theme = theme.unwrap_or_default();
-    HomeTemplate {
+    let is_admin = current_user.has_role(Admin::NAME);
+    let user = current_user.user;
+    let passkeys = crate::db::get_user_passkeys(&pool, user.id).await?;
+
+    // Only warn users with a second factor, the others don't have recovery codes
+    let recovery_codes_remaining = if crate::db::has_second_factor(&pool, user.id).await? {
+        let remaining = crate::db::count_unused_recovery_codes(&pool, user.id).await?;
+        Some(remaining).filter(|x| *x <= RECOVERY_CODE_LOW_THRESHOLD)
+    } else {
+        None
+    };
+
+    let has_password = user.provider == AuthProvider::Local;
+    let is_guest = user.provider == AuthProvider::Guest;
+
+    Ok(HomeTemplate {
         theme,
         user: Some(user),
-    }
+        passkeys,
+        recovery_codes_remaining,
+        has_password,
+        is_guest,
+        is_admin,
+    })
 }
 
 #[derive(Template)]
@@ -38,11 +103,284 @@ async fn home(CurrentUser(user): CurrentUser, UserTheme(theme): UserTheme) -> Ho
 struct LoginTemplate {
     theme: Theme,
     user: Option<User>,
+    email_sent: bool,
+    recovery_error: bool,
+    disabled: bool,
+    email_enabled: bool,
+    local_auth_enabled: bool,
+    local_error: Option<&'static str>,
+    providers: Vec<ProviderLink>,
+}
+
+/// A login button of a provider.
+struct ProviderLink {
+    name: String,
+    button: LoginButton,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LoginQuery {
+    #[serde(default)]
+    email_sent: bool,
+    #[serde(default)]
+    recovery_error: bool,
+    #[serde(default)]
+    disabled: bool,
+    local_error: Option<String>,
+}
+
+/// Returns the message to display for the error codes the auth routes redirect with.
+fn form_error_message(error: &str) -> &'static str {
+    match error {
+        "invalid" => {
+            "Invalid username or password, after too many failed attempts try again later."
+        }
+        "invalid_username" => {
+            "The username must have 3 to 32 letters, numbers, '_', '-' or '.' characters."
+        }
+        "username_taken" => "The username is already taken.",
+        "password_too_short" => "The password must have at least 8 characters.",
+        "password_too_long" => "The password must have at most 128 characters.",
+        "password_mismatch" => "The passwords don't match.",
+        "invalid_password" => "The current password is not correct.",
+        _ => "Something went wrong.",
+    }
+}
+
+async fn login(
+    user: Option<CurrentUser>,
+    UserTheme(theme): UserTheme,
+    State(local_auth): State<LocalAuthConfig>,
+    State(mailer): State<Option<SharedMailer>>,
+    State(providers): State<SharedProviders>,
+    Query(query): Query<LoginQuery>,
+) -> LoginTemplate {
+    let theme = theme.unwrap_or_default();
+    let providers = providers
+        .iter()
+        .map(|x| ProviderLink {
+            name: x.provider.provider().to_string(),
+            button: x.provider.login_button(),
+        })
+        .collect();
+
+    // Guests can sign in to keep their data
+    LoginTemplate {
+        theme,
+        user: user.map(|x| x.user),
+        email_sent: query.email_sent,
+        recovery_error: query.recovery_error,
+        disabled: query.disabled,
+        email_enabled: mailer.is_some(),
+        local_auth_enabled: local_auth.enabled,
+        local_error: query.local_error.as_deref().map(form_error_message),
+        providers,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email_login.html")]
+struct EmailLoginTemplate {
+    theme: Theme,
+    user: Option<User>,
+    token: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct EmailLoginQuery {
+    token: String,
+}
+
+/// The page of the sign-in links, the token is only used once the user confirms.
+async fn email_login(
+    user: Option<CurrentUser>,
+    UserTheme(theme): UserTheme,
+    Query(query): Query<EmailLoginQuery>,
+) -> EmailLoginTemplate {
+    let theme = theme.unwrap_or_default();
+    EmailLoginTemplate {
+        theme,
+        user: user.map(|x| x.user),
+        token: query.token,
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct FormErrorQuery {
+    error: Option<String>,
+    #[serde(default)]
+    updated: bool,
+}
+
+#[derive(Template)]
+#[template(path = "register.html")]
+struct RegisterTemplate {
+    theme: Theme,
+    user: Option<User>,
+    error: Option<&'static str>,
+}
+
+async fn register(
+    user: Option<CurrentUser>,
+    UserTheme(theme): UserTheme,
+    State(local_auth): State<LocalAuthConfig>,
+    Query(query): Query<FormErrorQuery>,
+) -> Result<RegisterTemplate, StatusCode> {
+    if !local_auth.enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let theme = theme.unwrap_or_default();
+    Ok(RegisterTemplate {
+        theme,
+        user: user.map(|x| x.user),
+        error: query.error.as_deref().map(form_error_message),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "password_settings.html")]
+struct PasswordSettingsTemplate {
+    theme: Theme,
+    user: Option<User>,
+    error: Option<&'static str>,
+    updated: bool,
+}
+
+async fn password_settings(
+    CurrentUser { user, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(local_auth): State<LocalAuthConfig>,
+    Query(query): Query<FormErrorQuery>,
+) -> Result<PasswordSettingsTemplate, StatusCode> {
+    if !local_auth.enabled || user.provider != AuthProvider::Local {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let theme = theme.unwrap_or_default();
+    Ok(PasswordSettingsTemplate {
+        theme,
+        user: Some(user),
+        error: query.error.as_deref().map(form_error_message),
+        updated: query.updated,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "sessions.html")]
+struct SessionsTemplate {
+    theme: Theme,
+    user: Option<User>,
+    sessions: Vec<UserSessionInfo>,
+}
+
+async fn sessions_settings(
+    CurrentUser { user, session, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(session_store): State<SharedSessionStore>,
+) -> Result<SessionsTemplate, AppError> {
+    let theme = theme.unwrap_or_default();
+    let sessions = session_store
+        .list_for_user(user.id, Some(&session.token_hash))
+        .await?;
+
+    Ok(SessionsTemplate {
+        theme,
+        user: Some(user),
+        sessions,
+    })
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ErrorQuery {
+    #[serde(default)]
+    error: bool,
+}
+
+#[derive(Template)]
+#[template(path = "two_factor.html")]
+struct TwoFactorTemplate {
+    theme: Theme,
+    user: Option<User>,
+    error: bool,
 }
 
-async fn login(UserTheme(theme): UserTheme) -> LoginTemplate {
+async fn two_factor(
+    PendingSecondFactorUser(_): PendingSecondFactorUser,
+    UserTheme(theme): UserTheme,
+    Query(query): Query<ErrorQuery>,
+) -> TwoFactorTemplate {
     let theme = theme.unwrap_or_default();
-    LoginTemplate { theme, user: None }
+    TwoFactorTemplate {
+        theme,
+        user: None,
+        error: query.error,
+    }
+}
+
+struct TotpSetup {
+    secret: String,
+    otpauth_url: String,
+    qr_code_base64: String,
+}
+
+#[derive(Template)]
+#[template(path = "two_factor_settings.html")]
+struct TwoFactorSettingsTemplate {
+    theme: Theme,
+    user: Option<User>,
+    enabled: bool,
+    setup: Option<TotpSetup>,
+    recovery_codes_remaining: i64,
+    error: bool,
+}
+
+async fn two_factor_settings(
+    CurrentUser { user, .. }: CurrentUser,
+    UserTheme(theme): UserTheme,
+    State(pool): State<DbPool>,
+    Query(query): Query<ErrorQuery>,
+) -> Result<TwoFactorSettingsTemplate, AppError> {
+    let theme = theme.unwrap_or_default();
+    let user_totp = crate::db::get_user_totp(&pool, user.id).await?;
+    let enabled = user_totp.as_ref().is_some_and(|x| x.confirmed_at.is_some());
+
+    // The secret is created from `/api/auth/totp/setup`, and shown until the user confirms it
+    let setup = match user_totp.filter(|x| x.confirmed_at.is_none()) {
+        Some(user_totp) => {
+            let totp = totp::build_totp(&user_totp.secret, &user.username)?;
+            let qr_code_base64 = totp
+                .get_qr_base64()
+                .map_err(|err| anyhow::anyhow!("Failed to generate QR code: {err}"))?;
+
+            Some(TotpSetup {
+                secret: user_totp.secret,
+                otpauth_url: totp.get_url(),
+                qr_code_base64,
+            })
+        }
+        None => None,
+    };
+
+    let recovery_codes_remaining = crate::db::count_unused_recovery_codes(&pool, user.id).await?;
+
+    Ok(TwoFactorSettingsTemplate {
+        theme,
+        user: Some(user),
+        enabled,
+        setup,
+        recovery_codes_remaining,
+        error: query.error,
+    })
+}
+
+// The recovery codes are only stored hashed, so this is the only time they can be shown
+#[derive(Template)]
+#[template(path = "recovery_codes.html")]
+pub(crate) struct RecoveryCodesTemplate {
+    pub theme: Theme,
+    pub user: Option<User>,
+    pub codes: Vec<String>,
 }
 
 #[derive(Template)]
@@ -53,7 +391,8 @@ struct ErrorTemplate {
     error: PageError,
 }
 
-async fn not_found(UserTheme(theme): UserTheme) -> ErrorTemplate {
+/// The page of the routes that don't exist, applications can set it as the fallback of their router.
+pub async fn not_found(UserTheme(theme): UserTheme) -> impl IntoResponse {
     let theme = theme.unwrap_or_default();
 
     ErrorTemplate {
@@ -73,7 +412,13 @@ pub async fn error_handler_middleware(
 ) -> axum::response::Response {
     let response = next.run(request).await;
 
-    if response.status().is_client_error() || response.status().is_server_error() {
+    // Json errors are meant for api clients, not to be displayed
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|x| x.as_bytes().starts_with(b"application/json"));
+
+    if !is_json && (response.status().is_client_error() || response.status().is_server_error()) {
         let theme = theme.unwrap_or_default();
         let status = response.status();
         let message = status
@@ -92,19 +437,26 @@ pub async fn error_handler_middleware(
     response
 }
 
-async fn auth_middleware(
+/// Sends away the users that already signed in, or that only need to verify the second factor.
+async fn sign_in_middleware(
     user: Option<CurrentUser>,
+    pending_user: Option<PendingSecondFactorUser>,
     request: Request,
     next: Next,
 ) -> axum::response::Response {
-    match user {
-        None if request.uri().path() != "/login" => Redirect::to("/login").into_response(),
-        Some(_) if request.uri().path() == "/login" => Redirect::to("/").into_response(),
+    let is_two_factor_page = request.uri().path() == "/2fa";
+    match (user, pending_user) {
+        (None, Some(_)) if !is_two_factor_page => Redirect::to("/2fa").into_response(),
+        (None, None) if is_two_factor_page => Redirect::to("/login").into_response(),
+        // Guests can still visit the login pages to upgrade their account
+        (Some(x), _) if x.user.provider != AuthProvider::Guest || is_two_factor_page => {
+            Redirect::to("/").into_response()
+        }
         _ => next.run(request).await,
     }
 }
 
-mod filters {
+pub(crate) mod filters {
     pub fn take<T: std::fmt::Display>(s: T, count: usize) -> ::askama::Result<String> {
         let s = s.to_string();
         Ok(s[0..count].to_string())
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use webauthn_rs::Webauthn;
+
+use crate::config::SharedAuthConfig;
+use crate::db::DbPool;
+use crate::mailer::SharedMailer;
+use crate::providers::SharedProviders;
+use crate::routes::LocalAuthConfig;
+use crate::session_store::SharedSessionStore;
+
+use super::{RateLimiter, SessionCache, SessionConfig};
+
+/// The state shared by all the routes, the handlers extract the parts they need with `State`.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: DbPool,
+    pub config: SharedAuthConfig,
+    /// The email sign-in is disabled without a mailer.
+    pub mailer: Option<SharedMailer>,
+    pub webauthn: Arc<Webauthn>,
+    pub session_cache: SessionCache,
+    pub session_store: SharedSessionStore,
+    pub providers: SharedProviders,
+    /// Limits the sign in attempts that send emails or create users.
+    pub rate_limiter: RateLimiter,
+    /// Shared by the requests to the providers, so the connections are reused.
+    pub http_client: reqwest::Client,
+}
+
+impl FromRef<AppState> for DbPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedAuthConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<SharedMailer> {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Webauthn> {
+    fn from_ref(state: &AppState) -> Self {
+        state.webauthn.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.session_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedSessionStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.session_store.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedProviders {
+    fn from_ref(state: &AppState) -> Self {
+        state.providers.clone()
+    }
+}
+
+impl FromRef<AppState> for RateLimiter {
+    fn from_ref(state: &AppState) -> Self {
+        state.rate_limiter.clone()
+    }
+}
+
+impl FromRef<AppState> for reqwest::Client {
+    fn from_ref(state: &AppState) -> Self {
+        state.http_client.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.session
+    }
+}
+
+impl FromRef<AppState> for LocalAuthConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.local_auth
+    }
+}
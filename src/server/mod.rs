@@ -1,17 +1,55 @@
+use std::{convert::Infallible, marker::PhantomData, net::SocketAddr, ops::Deref};
+
+use crate::db::DbPool;
 use askama_axum::IntoResponse;
-use axum::extract::FromRequestParts;
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
 use axum::http::request::Parts;
-use axum::http::StatusCode;
-use axum::{async_trait, Extension};
+use axum::http::{header, StatusCode};
 use axum_extra::extract::CookieJar;
-use sqlx::SqlitePool;
 
-use crate::constants::{COOKIE_AUTH_SESSION, COOKIE_THEME};
-use crate::misc::Theme;
-use crate::models::User;
+use crate::config::SharedAuthConfig;
+use crate::constants::{
+    COOKIE_AUTH_REMEMBER_ME, COOKIE_AUTH_SESSION, COOKIE_THEME, SESSION_LAST_SEEN_UPDATE_INTERVAL,
+};
+use crate::misc::{token::hash_token, Theme};
+use crate::models::{Permission, Role, User, UserSession};
+use crate::session_store::{SessionStore, SharedSessionStore};
 
-#[derive(Debug)]
-pub struct CurrentUser(pub User);
+mod app_state;
+mod rate_limit;
+mod require_auth;
+mod session;
+mod session_cache;
+mod trusted_proxies;
+
+pub use app_state::AppState;
+pub use rate_limit::RateLimiter;
+pub use require_auth::RequireAuth;
+pub use session::{session_cookie, session_cookie_middleware, SessionConfig};
+pub use session_cache::{SessionCache, SessionCacheConfig};
+pub use trusted_proxies::TrustedProxies;
+
+use session::UpdatedSessionCookie;
+
+/// The signed in user, its roles and the current session.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CurrentUser {
+    #[serde(flatten)]
+    pub user: User,
+    pub roles: Vec<Role>,
+    pub session: UserSession,
+}
+
+impl CurrentUser {
+    pub fn has_role(&self, role_name: &str) -> bool {
+        self.roles.iter().any(|x| x.name == role_name)
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.roles.iter().any(|x| x.has_permission(permission))
+    }
+}
 
 pub struct UnauthorizedUser;
 impl IntoResponse for UnauthorizedUser {
@@ -24,16 +62,181 @@ impl IntoResponse for UnauthorizedUser {
 impl<S> FromRequestParts<S> for CurrentUser
 where
     S: Send + Sync,
+    AppState: FromRef<S>,
 {
     type Rejection = UnauthorizedUser;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let Extension(pool) = Extension::<SqlitePool>::from_request_parts(parts, state)
+        // Already extracted by the `RequireAuth` layer
+        if let Some(current_user) = parts.extensions.get::<CurrentUser>() {
+            return Ok(current_user.clone());
+        }
+
+        let AppState {
+            pool,
+            config,
+            session_cache,
+            session_store,
+            ..
+        } = AppState::from_ref(state);
+        let session_config = config.session;
+        let cookies = CookieJar::from_request_parts(parts, state)
             .await
             .map_err(|err| {
                 tracing::error!("{err}");
                 UnauthorizedUser
             })?;
+
+        let Some(session_cookie) = cookies.get(COOKIE_AUTH_SESSION) else {
+            return Err(UnauthorizedUser);
+        };
+
+        let token_hash = hash_token(session_cookie.value());
+        let user = match session_cache.get(&token_hash) {
+            Some(x) => Some(x),
+            None => {
+                let generation = session_cache.generation();
+                let user = get_current_user(&*session_store, &pool, &token_hash)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!("failed to get current user: {err}");
+                        UnauthorizedUser
+                    })?;
+
+                if let Some((user, session, roles)) = &user {
+                    session_cache.insert(generation, &token_hash, user, session, roles);
+                }
+
+                user
+            }
+        };
+
+        let Some((user, mut session, roles)) = user else {
+            remove_stale_session_cookie(parts, &*session_store, &token_hash).await;
+            return Err(UnauthorizedUser);
+        };
+
+        // Only update the last activity from time to time to not write on each request
+        let now = chrono::offset::Utc::now().naive_utc();
+        let is_stale = session
+            .last_seen_at
+            .is_none_or(|x| x + SESSION_LAST_SEEN_UPDATE_INTERVAL < now);
+
+        if is_stale {
+            let expires_at = session_config.expires_at(session.created_at, now, session.persistent);
+            match session_store.touch(&token_hash, now, expires_at).await {
+                Ok(()) => {
+                    session.last_seen_at = Some(now);
+                    session.expires_at = expires_at;
+                    session_cache.touch(&token_hash, now, expires_at);
+
+                    // A persistent cookie must outlive the extended session too
+                    let updated_cookie = parts.extensions.get::<UpdatedSessionCookie>();
+                    if let Some(updated_cookie) = updated_cookie.filter(|_| session.persistent) {
+                        let max_age = (expires_at - now).to_std().unwrap_or_default();
+                        updated_cookie.set(session::session_cookie(
+                            session_cookie.value().to_owned(),
+                            Some(max_age),
+                        ));
+                    }
+                }
+                Err(err) => tracing::error!("failed to update session last activity: {err}"),
+            }
+        }
+
+        Ok(CurrentUser {
+            user,
+            roles,
+            session,
+        })
+    }
+}
+
+async fn get_current_user(
+    session_store: &dyn SessionStore,
+    pool: &DbPool,
+    token_hash: &str,
+) -> Result<Option<(User, UserSession, Vec<Role>)>, anyhow::Error> {
+    let Some((user, session)) =
+        crate::session_store::get_session_user(session_store, pool, token_hash, false).await?
+    else {
+        return Ok(None);
+    };
+
+    let roles = crate::db::get_user_roles(pool, user.id).await?;
+    Ok(Some((user, session, roles)))
+}
+
+/// A role that can be required with [`RequireRole`].
+pub trait RoleName {
+    const NAME: &'static str;
+}
+
+/// The role with access to the administration of the app.
+#[derive(Debug)]
+pub struct Admin;
+
+impl RoleName for Admin {
+    const NAME: &'static str = "admin";
+}
+
+/// The current user, rejects with `403` if it doesn't have the role `R`.
+#[derive(Debug)]
+pub struct RequireRole<R: RoleName>(pub CurrentUser, PhantomData<R>);
+
+impl<R: RoleName> Deref for RequireRole<R> {
+    type Target = CurrentUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+    R: RoleName,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let current_user = CurrentUser::from_request_parts(parts, state)
+            .await
+            .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+        if !current_user.has_role(R::NAME) {
+            tracing::warn!(
+                "user '{}' tried to access a resource that requires the '{}' role",
+                current_user.user.id,
+                R::NAME
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(RequireRole(current_user, PhantomData))
+    }
+}
+
+/// An user that signed in with a provider but still needs to verify the second factor.
+#[derive(Debug)]
+pub struct PendingSecondFactorUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PendingSecondFactorUser
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = UnauthorizedUser;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AppState {
+            pool,
+            session_store,
+            ..
+        } = AppState::from_ref(state);
         let cookies = CookieJar::from_request_parts(parts, state)
             .await
             .map_err(|err| {
@@ -45,20 +248,139 @@ where
             return Err(UnauthorizedUser);
         };
 
-        let user = crate::db::get_user_by_session_id(&pool, session_cookie.value())
-            .await
-            .map_err(|err| {
-                tracing::error!("failed to get current user: {err}");
-                UnauthorizedUser
-            })?;
+        let token_hash = hash_token(session_cookie.value());
+        let user =
+            crate::session_store::get_session_user(&*session_store, &pool, &token_hash, true)
+                .await
+                .map_err(|err| {
+                    tracing::error!("failed to get pending user: {err}");
+                    UnauthorizedUser
+                })?;
 
         match user {
-            Some(x) => Ok(CurrentUser(x)),
-            None => Err(UnauthorizedUser),
+            Some((user, _)) => Ok(PendingSecondFactorUser(user)),
+            None => {
+                remove_stale_session_cookie(parts, &*session_store, &token_hash).await;
+                Err(UnauthorizedUser)
+            }
         }
     }
 }
 
+/// Removes the session cookie at the end of the request if its session expired or no longer exists,
+/// the session may also be valid but not for the extractor that looked for it, like a pending session.
+async fn remove_stale_session_cookie(
+    parts: &Parts,
+    session_store: &dyn SessionStore,
+    token_hash: &str,
+) {
+    let Some(updated_cookie) = parts.extensions.get::<UpdatedSessionCookie>() else {
+        return;
+    };
+
+    let now = chrono::offset::Utc::now().naive_utc();
+    match session_store.get(token_hash).await {
+        Ok(Some(session)) if session.expires_at > now => {}
+        Ok(Some(_)) => {
+            if let Err(err) = session_store.delete(token_hash).await {
+                tracing::error!("failed to delete expired session: {err}");
+            }
+
+            updated_cookie.remove();
+        }
+        Ok(None) => updated_cookie.remove(),
+        Err(err) => tracing::error!("failed to get user session: {err}"),
+    }
+}
+
+/// Information about the client making the request, stored with the sessions.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AppState { config, .. } = AppState::from_ref(state);
+        let ip_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| {
+                let trusted_proxies = &config.trusted_proxies;
+                trusted_proxies.client_ip(addr.ip(), &parts.headers)
+            })
+            .map(|ip| ip.to_canonical().to_string());
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.chars().take(512).collect());
+
+        Ok(ClientInfo {
+            ip_address,
+            user_agent,
+        })
+    }
+}
+
+/// What is needed to create the session of an user that is signing in.
+#[derive(Debug, Clone)]
+pub struct LoginContext {
+    pub client: ClientInfo,
+    pub config: SharedAuthConfig,
+    pub session_config: SessionConfig,
+    pub session_cache: SessionCache,
+    pub session_store: SharedSessionStore,
+    /// Whether the user chose to be kept signed in, this is carried through the sign in flows
+    /// with the `auth_remember_me` cookie.
+    pub remember_me: bool,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for LoginContext
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(client) = ClientInfo::from_request_parts(parts, state).await;
+        let AppState {
+            config,
+            session_cache,
+            session_store,
+            ..
+        } = AppState::from_ref(state);
+        let session_config = config.session;
+
+        let remember_me = CookieJar::from_request_parts(parts, state)
+            .await
+            .is_ok_and(|x| {
+                x.get(COOKIE_AUTH_REMEMBER_ME)
+                    .is_some_and(|x| x.value() == "true")
+            });
+
+        Ok(LoginContext {
+            client,
+            config,
+            session_config,
+            session_cache,
+            session_store,
+            remember_me,
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct UserTheme(pub Option<Theme>);
 
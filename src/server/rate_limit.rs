@@ -0,0 +1,88 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+use crate::constants::RATE_LIMIT_MAX_KEYS;
+
+/// Counts the attempts of each key, like the sign-in emails sent to an address, in fixed windows.
+///
+/// The counts are kept in memory, so each server limits the attempts it receives. Only the most recently
+/// used keys are kept, the others are forgotten.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    windows: Arc<Mutex<LruCache<String, Window>>>,
+}
+
+#[derive(Debug)]
+struct Window {
+    started_at: Instant,
+    attempts: u32,
+}
+
+impl RateLimiter {
+    pub fn new(max_keys: usize) -> Self {
+        let max_keys = NonZeroUsize::new(max_keys).unwrap_or(NonZeroUsize::MIN);
+
+        RateLimiter {
+            windows: Arc::new(Mutex::new(LruCache::new(max_keys))),
+        }
+    }
+
+    /// Records an attempt, returns `false` if there were already `limit` attempts in the last `window`.
+    pub fn check(&self, key: &str, limit: u32, window: Duration) -> bool {
+        let mut windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let now = Instant::now();
+        let current = windows.get_or_insert_mut(key.to_owned(), || Window {
+            started_at: now,
+            attempts: 0,
+        });
+
+        if now.duration_since(current.started_at) >= window {
+            current.started_at = now;
+            current.attempts = 0;
+        }
+
+        if current.attempts >= limit {
+            return false;
+        }
+
+        current.attempts += 1;
+        true
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(RATE_LIMIT_MAX_KEYS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn limits_each_key() {
+        let rate_limiter = RateLimiter::default();
+
+        assert!(rate_limiter.check("a", 2, MINUTE));
+        assert!(rate_limiter.check("a", 2, MINUTE));
+        assert!(!rate_limiter.check("a", 2, MINUTE));
+        assert!(rate_limiter.check("b", 2, MINUTE));
+    }
+
+    #[test]
+    fn starts_a_new_window() {
+        let rate_limiter = RateLimiter::default();
+
+        assert!(rate_limiter.check("a", 1, Duration::ZERO));
+        assert!(rate_limiter.check("a", 1, Duration::ZERO));
+    }
+}
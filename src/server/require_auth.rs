@@ -0,0 +1,165 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    extract::{FromRequestParts, OriginalUri, Request},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use tower::{Layer, Service};
+
+use super::{AppState, CurrentUser, PendingSecondFactorUser};
+use crate::models::Permission;
+
+type Policy = Arc<dyn Fn(&CurrentUser) -> bool + Send + Sync>;
+
+/// A layer that only lets through the requests of signed in users that satisfy all its policies.
+///
+/// Requests to `/api` are rejected with a `401` or `403` json response, other requests are
+/// redirected to the login page or rejected with a `403`.
+///
+/// ```ignore
+/// Router::new()
+///     .route("/admin", get(admin))
+///     .route_layer(RequireAuth::new(state).role("admin").redirect_to("/login"))
+/// ```
+#[derive(Clone)]
+pub struct RequireAuth {
+    state: AppState,
+    policies: Vec<Policy>,
+    redirect_to: String,
+}
+
+impl RequireAuth {
+    /// Layers can't extract the state of the router, so the user is looked up with `state`.
+    pub fn new(state: &AppState) -> Self {
+        RequireAuth {
+            state: state.clone(),
+            policies: Vec::new(),
+            redirect_to: "/login".to_owned(),
+        }
+    }
+
+    /// Requires the user to have the given role.
+    pub fn role(self, role_name: impl Into<String>) -> Self {
+        let role_name = role_name.into();
+        self.policy(move |user| user.has_role(&role_name))
+    }
+
+    /// Requires the user to have the given permission in any of its roles.
+    pub fn permission(self, permission: Permission) -> Self {
+        self.policy(move |user| user.has_permission(permission))
+    }
+
+    /// Requires the user to satisfy the given policy.
+    pub fn policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&CurrentUser) -> bool + Send + Sync + 'static,
+    {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Where to redirect the page requests of anonymous users, defaults to `/login`.
+    pub fn redirect_to(mut self, path: impl Into<String>) -> Self {
+        self.redirect_to = path.into();
+        self
+    }
+
+    async fn authorize(&self, parts: &mut Parts) -> Result<(), Response> {
+        let path = match parts.extensions.get::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri.path().to_owned(),
+            None => parts.uri.path().to_owned(),
+        };
+
+        let is_api = path == "/api" || path.starts_with("/api/");
+        let reject = |status: StatusCode| {
+            if is_api {
+                let message = status.canonical_reason().unwrap_or_default();
+                (status, Json(serde_json::json!({ "error": message }))).into_response()
+            } else {
+                status.into_response()
+            }
+        };
+
+        let Ok(current_user) = CurrentUser::from_request_parts(parts, &self.state).await else {
+            if is_api {
+                return Err(reject(StatusCode::UNAUTHORIZED));
+            }
+
+            // Users with a pending second factor only need to finish signing in
+            if PendingSecondFactorUser::from_request_parts(parts, &self.state)
+                .await
+                .is_ok()
+            {
+                return Err(Redirect::to("/2fa").into_response());
+            }
+
+            return Err(Redirect::to(&self.redirect_to).into_response());
+        };
+
+        if !self.policies.iter().all(|policy| policy(&current_user)) {
+            tracing::warn!(
+                "user '{}' is not authorized to access '{path}'",
+                current_user.user.id
+            );
+            return Err(reject(StatusCode::FORBIDDEN));
+        }
+
+        // The handlers get the user from the extensions instead of looking it up again
+        parts.extensions.insert(current_user);
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for RequireAuth {
+    type Service = RequireAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireAuthService {
+            inner,
+            require_auth: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequireAuthService<S> {
+    inner: S,
+    require_auth: RequireAuth,
+}
+
+impl<S> Service<Request> for RequireAuthService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The ready service is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let require_auth = self.require_auth.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            if let Err(response) = require_auth.authorize(&mut parts).await {
+                return Ok(response);
+            }
+
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::cookie::SameSite;
+use chrono::NaiveDateTime;
+use cookie::Cookie;
+
+use crate::config::ConfigReader;
+use crate::constants::{
+    COOKIE_AUTH_SESSION, SESSION_ABSOLUTE_TIMEOUT, SESSION_IDLE_TIMEOUT,
+    SESSION_LAST_SEEN_UPDATE_INTERVAL, SESSION_PERSISTENT_ABSOLUTE_TIMEOUT,
+    SESSION_PERSISTENT_TIMEOUT,
+};
+
+/// How long the sessions last, set in minutes with `SESSION_IDLE_TIMEOUT_MINUTES`,
+/// `SESSION_PERSISTENT_TIMEOUT_MINUTES`, `SESSION_ABSOLUTE_TIMEOUT_MINUTES` and
+/// `SESSION_PERSISTENT_ABSOLUTE_TIMEOUT_MINUTES`.
+///
+/// A session expires once it was not used for the idle timeout, or the persistent timeout if the user
+/// chose to be kept signed in. While it is used it is extended up to the absolute timeout since the user signed in,
+/// or the persistent absolute timeout for the persistent sessions.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionConfig {
+    pub idle_timeout: Duration,
+    pub persistent_timeout: Duration,
+    pub absolute_timeout: Duration,
+    pub persistent_absolute_timeout: Duration,
+}
+
+impl SessionConfig {
+    pub fn from_config(reader: &mut ConfigReader) -> Self {
+        let config = SessionConfig {
+            idle_timeout: minutes_from_config(reader, "SESSION_IDLE_TIMEOUT_MINUTES")
+                .unwrap_or(SESSION_IDLE_TIMEOUT),
+            persistent_timeout: minutes_from_config(reader, "SESSION_PERSISTENT_TIMEOUT_MINUTES")
+                .unwrap_or(SESSION_PERSISTENT_TIMEOUT),
+            absolute_timeout: minutes_from_config(reader, "SESSION_ABSOLUTE_TIMEOUT_MINUTES")
+                .unwrap_or(SESSION_ABSOLUTE_TIMEOUT),
+            persistent_absolute_timeout: minutes_from_config(
+                reader,
+                "SESSION_PERSISTENT_ABSOLUTE_TIMEOUT_MINUTES",
+            )
+            .unwrap_or(SESSION_PERSISTENT_ABSOLUTE_TIMEOUT),
+        };
+
+        // The last activity is only updated from time to time, so a shorter session would expire while it is used
+        if config.idle_timeout.min(config.persistent_timeout) <= SESSION_LAST_SEEN_UPDATE_INTERVAL {
+            reader.error(format!(
+                "The session timeouts must be longer than {} minutes",
+                SESSION_LAST_SEEN_UPDATE_INTERVAL.as_secs() / 60
+            ));
+        }
+
+        if config.idle_timeout > config.absolute_timeout {
+            reader.error("The session idle timeout can't be longer than the absolute timeout");
+        }
+
+        if config.persistent_timeout > config.persistent_absolute_timeout {
+            reader.error(
+                "The session persistent timeout can't be longer than the persistent absolute timeout",
+            );
+        }
+
+        config
+    }
+
+    /// Returns how long a session lasts without being used.
+    pub fn session_timeout(&self, persistent: bool) -> Duration {
+        if persistent {
+            self.persistent_timeout
+        } else {
+            self.idle_timeout
+        }
+    }
+
+    /// Returns how long a session can be extended since the user signed in.
+    pub fn absolute_timeout(&self, persistent: bool) -> Duration {
+        if persistent {
+            self.persistent_absolute_timeout
+        } else {
+            self.absolute_timeout
+        }
+    }
+
+    /// Returns when a session created at `created_at` expires if it is used at `now`.
+    pub fn expires_at(
+        &self,
+        created_at: NaiveDateTime,
+        now: NaiveDateTime,
+        persistent: bool,
+    ) -> NaiveDateTime {
+        (now + self.session_timeout(persistent)).min(created_at + self.absolute_timeout(persistent))
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            idle_timeout: SESSION_IDLE_TIMEOUT,
+            persistent_timeout: SESSION_PERSISTENT_TIMEOUT,
+            absolute_timeout: SESSION_ABSOLUTE_TIMEOUT,
+            persistent_absolute_timeout: SESSION_PERSISTENT_ABSOLUTE_TIMEOUT,
+        }
+    }
+}
+
+fn minutes_from_config(reader: &mut ConfigReader, name: &str) -> Option<Duration> {
+    reader.parse_with(name, "a number of minutes", |x| {
+        x.parse::<u64>()
+            .ok()
+            .filter(|x| *x > 0)
+            .map(|x| Duration::from_secs(x * 60))
+    })
+}
+
+/// Returns the session cookie, without a max age it is removed when the browser is closed.
+pub fn session_cookie(session_id: String, max_age: Option<Duration>) -> Cookie<'static> {
+    let mut cookie: Cookie = Cookie::build((COOKIE_AUTH_SESSION, session_id))
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .path("/")
+        .into();
+
+    if let Some(max_age) = max_age {
+        cookie.set_max_age(cookie::time::Duration::milliseconds(
+            max_age.as_millis() as i64
+        ));
+    }
+
+    cookie
+}
+
+/// Where the extractors leave the session cookie of an extended session, or the removal of a stale one,
+/// the extractors can't set cookies so [`session_cookie_middleware`] adds it to the response.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UpdatedSessionCookie(Arc<Mutex<Option<Cookie<'static>>>>);
+
+impl UpdatedSessionCookie {
+    pub fn set(&self, cookie: Cookie<'static>) {
+        if let Ok(mut updated_cookie) = self.0.lock() {
+            *updated_cookie = Some(cookie);
+        }
+    }
+
+    /// Removes the session cookie at the end of the request.
+    pub fn remove(&self) {
+        let mut cookie = Cookie::new(COOKIE_AUTH_SESSION, "");
+        cookie.set_path("/");
+        cookie.make_removal();
+        self.set(cookie);
+    }
+
+    fn take(&self) -> Option<Cookie<'static>> {
+        self.0.lock().ok().and_then(|mut x| x.take())
+    }
+}
+
+/// Re-issues the session cookie when the session was extended during the request,
+/// or removes it when it was expired.
+pub async fn session_cookie_middleware(mut request: Request, next: Next) -> Response {
+    let updated_cookie = UpdatedSessionCookie::default();
+    request.extensions_mut().insert(updated_cookie.clone());
+
+    let mut response = next.run(request).await;
+    let Some(cookie) = updated_cookie.take() else {
+        return response;
+    };
+
+    // The handler may have replaced or removed the cookie, like when signing out
+    let sets_session_cookie = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|x| x.to_str().ok())
+        .filter_map(|x| Cookie::parse(x).ok())
+        .any(|x| x.name() == COOKIE_AUTH_SESSION);
+
+    if !sets_session_cookie {
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn signed_in_at() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn caps_sessions_at_the_absolute_timeout() {
+        let config = SessionConfig {
+            idle_timeout: HOUR,
+            absolute_timeout: 3 * HOUR,
+            ..Default::default()
+        };
+
+        let created_at = signed_in_at();
+        let now = created_at + Duration::from_secs(30 * 60);
+        assert_eq!(config.expires_at(created_at, now, false), now + HOUR);
+
+        let now = created_at + Duration::from_secs(150 * 60);
+        assert_eq!(
+            config.expires_at(created_at, now, false),
+            created_at + 3 * HOUR
+        );
+
+        let now = created_at + 4 * HOUR;
+        assert_eq!(
+            config.expires_at(created_at, now, false),
+            created_at + 3 * HOUR
+        );
+    }
+
+    #[test]
+    fn caps_persistent_sessions_at_the_persistent_absolute_timeout() {
+        let config = SessionConfig {
+            idle_timeout: HOUR,
+            persistent_timeout: 2 * HOUR,
+            absolute_timeout: 3 * HOUR,
+            persistent_absolute_timeout: 5 * HOUR,
+        };
+
+        let created_at = signed_in_at();
+        let now = created_at + 2 * HOUR;
+        assert_eq!(config.expires_at(created_at, now, true), now + 2 * HOUR);
+        assert_eq!(
+            config.expires_at(created_at, now, false),
+            created_at + 3 * HOUR
+        );
+
+        let now = created_at + 4 * HOUR;
+        assert_eq!(
+            config.expires_at(created_at, now, true),
+            created_at + 5 * HOUR
+        );
+    }
+
+    #[test]
+    fn rejects_timeouts_shorter_than_the_last_activity_updates() {
+        let mut reader = ConfigReader::from_settings(&[("SESSION_IDLE_TIMEOUT_MINUTES", "5")]);
+        SessionConfig::from_config(&mut reader);
+        assert!(reader.finish().is_err());
+
+        let mut reader = ConfigReader::from_settings(&[("SESSION_IDLE_TIMEOUT_MINUTES", "6")]);
+        let config = SessionConfig::from_config(&mut reader);
+        assert!(reader.finish().is_ok());
+        assert_eq!(config.idle_timeout, Duration::from_secs(6 * 60));
+    }
+}
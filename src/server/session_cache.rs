@@ -0,0 +1,347 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+use lru::LruCache;
+use uuid::Uuid;
+
+use crate::config::ConfigReader;
+use crate::constants::{SESSION_CACHE_SIZE, SESSION_CACHE_TTL};
+use crate::models::{Role, User, UserSession};
+
+/// Keeps the users of the recently used sessions in memory, so most requests don't need to query the database.
+///
+/// The entries must be invalidated when a session is deleted or its user or roles change,
+/// the ttl only limits how long a change made by another process is missed. When the cache is full
+/// the least recently used session is evicted.
+///
+/// The hits and misses are recorded as the `session_cache_hits_total` and `session_cache_misses_total` counters,
+/// and the cached sessions as the `session_cache_entries` gauge.
+#[derive(Debug, Clone)]
+pub struct SessionCache {
+    inner: Arc<SessionCacheInner>,
+}
+
+#[derive(Debug)]
+struct SessionCacheInner {
+    entries: Mutex<LruCache<String, CacheEntry>>,
+    capacity: usize,
+    ttl: Duration,
+    generation: AtomicU64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    user: User,
+    session: UserSession,
+    roles: Vec<Role>,
+    cached_at: Instant,
+}
+
+/// How many sessions are cached and for how long, set with `SESSION_CACHE_SIZE`, `0` disables the cache,
+/// and `SESSION_CACHE_TTL_SECONDS`.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionCacheConfig {
+    pub capacity: usize,
+    pub ttl: Duration,
+}
+
+impl SessionCacheConfig {
+    pub fn from_config(reader: &mut ConfigReader) -> Self {
+        let capacity = reader.parse_with("SESSION_CACHE_SIZE", "a number of sessions", |x| {
+            x.parse::<usize>().ok()
+        });
+
+        let ttl = reader.parse_with("SESSION_CACHE_TTL_SECONDS", "a number of seconds", |x| {
+            x.parse::<u64>()
+                .ok()
+                .filter(|x| *x > 0)
+                .map(Duration::from_secs)
+        });
+
+        SessionCacheConfig {
+            capacity: capacity.unwrap_or(SESSION_CACHE_SIZE),
+            ttl: ttl.unwrap_or(SESSION_CACHE_TTL),
+        }
+    }
+}
+
+impl SessionCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        SessionCache {
+            inner: Arc::new(SessionCacheInner {
+                // The cache is not used when the capacity is 0
+                entries: Mutex::new(LruCache::new(
+                    NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+                )),
+                capacity,
+                ttl,
+                generation: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn from_config(config: &SessionCacheConfig) -> Self {
+        SessionCache::new(config.capacity, config.ttl)
+    }
+
+    /// Returns the cached user of a session, expired sessions are never returned.
+    pub fn get(&self, token_hash: &str) -> Option<(User, UserSession, Vec<Role>)> {
+        if self.inner.capacity == 0 {
+            return None;
+        }
+
+        let now = chrono::offset::Utc::now().naive_utc();
+        let mut entries = self.entries();
+        let result = match entries.get(token_hash) {
+            Some(entry) if self.is_fresh(entry) && entry.session.expires_at > now => Some((
+                entry.user.clone(),
+                entry.session.clone(),
+                entry.roles.clone(),
+            )),
+            Some(_) => {
+                entries.pop(token_hash);
+                record_entries(&entries);
+                None
+            }
+            None => None,
+        };
+
+        match result {
+            Some(_) => metrics::counter!("session_cache_hits_total").increment(1),
+            None => metrics::counter!("session_cache_misses_total").increment(1),
+        }
+
+        result
+    }
+
+    /// Returns the current generation, it must be read before querying the session
+    /// that is then passed to [`SessionCache::insert`].
+    pub fn generation(&self) -> u64 {
+        self.inner.generation.load(Ordering::Acquire)
+    }
+
+    /// Caches the user of a session, unless something was invalidated since `generation` was read,
+    /// in that case the session may have been read before the change.
+    pub fn insert(
+        &self,
+        generation: u64,
+        token_hash: &str,
+        user: &User,
+        session: &UserSession,
+        roles: &[Role],
+    ) {
+        if self.inner.capacity == 0 {
+            return;
+        }
+
+        // Checked while holding the lock, the invalidations also take it
+        let mut entries = self.entries();
+        if self.generation() != generation {
+            return;
+        }
+
+        // Evicts the least recently used session if the cache is full
+        entries.put(
+            token_hash.to_owned(),
+            CacheEntry {
+                user: user.clone(),
+                session: session.clone(),
+                roles: roles.to_vec(),
+                cached_at: Instant::now(),
+            },
+        );
+        record_entries(&entries);
+    }
+
+    /// Updates the cached session after it was extended, the entry still expires when it would have.
+    pub fn touch(&self, token_hash: &str, last_seen_at: NaiveDateTime, expires_at: NaiveDateTime) {
+        if let Some(entry) = self.entries().get_mut(token_hash) {
+            entry.session.last_seen_at = Some(last_seen_at);
+            entry.session.expires_at = expires_at;
+        }
+    }
+
+    /// Removes a session, like when signing out.
+    pub fn invalidate(&self, token_hash: &str) {
+        let mut entries = self.entries();
+
+        self.inner.generation.fetch_add(1, Ordering::AcqRel);
+        entries.pop(token_hash);
+        record_entries(&entries);
+    }
+
+    /// Removes all the sessions of an user, after its sessions, roles or details changed.
+    pub fn invalidate_user(&self, user_id: Uuid) {
+        self.invalidate_where(|entry| entry.user.id == user_id);
+    }
+
+    /// Removes all the sessions.
+    pub fn clear(&self) {
+        let mut entries = self.entries();
+
+        self.inner.generation.fetch_add(1, Ordering::AcqRel);
+        entries.clear();
+        record_entries(&entries);
+    }
+
+    fn entries(&self) -> MutexGuard<'_, LruCache<String, CacheEntry>> {
+        // The entries are always left valid, so they can still be used after a panic
+        self.inner
+            .entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry) -> bool {
+        entry.cached_at.elapsed() < self.inner.ttl
+    }
+
+    fn invalidate_where(&self, predicate: impl Fn(&CacheEntry) -> bool) {
+        // The generation is changed while holding the lock, to order it with the inserts
+        let mut entries = self.entries();
+
+        self.inner.generation.fetch_add(1, Ordering::AcqRel);
+        let keys = entries
+            .iter()
+            .filter(|(_, entry)| predicate(entry))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            entries.pop(&key);
+        }
+
+        record_entries(&entries);
+    }
+}
+
+fn record_entries(entries: &LruCache<String, CacheEntry>) {
+    metrics::gauge!("session_cache_entries").set(entries.len() as f64);
+}
+
+impl Default for SessionCacheConfig {
+    fn default() -> Self {
+        SessionCacheConfig {
+            capacity: SESSION_CACHE_SIZE,
+            ttl: SESSION_CACHE_TTL,
+        }
+    }
+}
+
+impl Default for SessionCache {
+    fn default() -> Self {
+        SessionCache::new(SESSION_CACHE_SIZE, SESSION_CACHE_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AuthProvider;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn session_of(user_id: Uuid) -> (User, UserSession) {
+        let now = chrono::offset::Utc::now().naive_utc();
+        let user = User {
+            id: user_id,
+            account_id: user_id.to_string(),
+            provider: AuthProvider::Guest,
+            username: "guest".to_owned(),
+            image_url: None,
+        };
+
+        let session = UserSession {
+            token_hash: String::new(),
+            user_id,
+            created_at: now,
+            expires_at: now + chrono::Duration::hours(1),
+            last_seen_at: None,
+            second_factor_pending: false,
+            persistent: false,
+        };
+
+        (user, session)
+    }
+
+    fn insert(cache: &SessionCache, token_hash: &str, user_id: Uuid) {
+        let (user, session) = session_of(user_id);
+        cache.insert(cache.generation(), token_hash, &user, &session, &[]);
+    }
+
+    #[test]
+    fn drops_inserts_read_before_an_invalidation() {
+        let cache = SessionCache::new(10, MINUTE);
+        let (user, session) = session_of(Uuid::new_v4());
+
+        // The session was read, then deleted before it was cached
+        let generation = cache.generation();
+        cache.invalidate("a");
+        cache.insert(generation, "a", &user, &session, &[]);
+        assert!(cache.get("a").is_none());
+
+        let generation = cache.generation();
+        cache.invalidate_user(Uuid::new_v4());
+        cache.insert(generation, "a", &user, &session, &[]);
+        assert!(cache.get("a").is_none());
+
+        cache.insert(cache.generation(), "a", &user, &session, &[]);
+        assert!(cache.get("a").is_some());
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_sessions() {
+        let cache = SessionCache::new(2, MINUTE);
+        insert(&cache, "a", Uuid::new_v4());
+        insert(&cache, "b", Uuid::new_v4());
+
+        assert!(cache.get("a").is_some());
+        insert(&cache, "c", Uuid::new_v4());
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn invalidates_the_sessions_of_an_user() {
+        let cache = SessionCache::new(10, MINUTE);
+        let user_id = Uuid::new_v4();
+        insert(&cache, "a", user_id);
+        insert(&cache, "b", user_id);
+        insert(&cache, "c", Uuid::new_v4());
+
+        cache.invalidate_user(user_id);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn expires_the_entries() {
+        let cache = SessionCache::new(10, Duration::ZERO);
+        insert(&cache, "a", Uuid::new_v4());
+        assert!(cache.get("a").is_none());
+
+        // The session itself expired before the ttl
+        let cache = SessionCache::new(10, MINUTE);
+        let (user, mut session) = session_of(Uuid::new_v4());
+        session.expires_at = session.created_at - chrono::Duration::seconds(1);
+        cache.insert(cache.generation(), "a", &user, &session, &[]);
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn is_disabled_without_capacity() {
+        let cache = SessionCache::new(0, MINUTE);
+        insert(&cache, "a", Uuid::new_v4());
+        assert!(cache.get("a").is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use std::net::IpAddr;
+
+use anyhow::Context;
+use axum::http::HeaderMap;
+
+use crate::config::ConfigReader;
+
+/// The proxies allowed to tell the ip of the client with the `X-Forwarded-For` header,
+/// set with `TRUSTED_PROXIES` as a comma separated list of addresses or networks like `10.0.0.0/8`.
+///
+/// If no proxy is trusted the header is ignored, otherwise any client could fake its ip.
+#[derive(Debug, Default, Clone)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    pub fn from_config(reader: &mut ConfigReader) -> Self {
+        let Some(value) = reader.get("TRUSTED_PROXIES") else {
+            return Default::default();
+        };
+
+        let mut networks = Vec::new();
+        for network in value.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            match parse_network(network) {
+                Ok(network) => networks.push(network),
+                Err(err) => reader.error(format!("Invalid 'TRUSTED_PROXIES': {err}")),
+            }
+        }
+
+        TrustedProxies { networks }
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks
+            .iter()
+            .any(|(network, prefix_len)| network_contains(*network, *prefix_len, ip))
+    }
+
+    /// Returns the ip of the client, the `X-Forwarded-For` header is read from right to left
+    /// while the addresses are trusted proxies, the first untrusted address is the client.
+    pub fn client_ip(&self, peer_ip: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(peer_ip) {
+            return peer_ip;
+        }
+
+        let forwarded_ips = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(','))
+            .map(|x| x.trim().parse::<IpAddr>())
+            .collect::<Vec<_>>();
+
+        let mut client_ip = peer_ip;
+        for ip in forwarded_ips.into_iter().rev() {
+            // A malformed entry can't be trusted, so the last valid address is used
+            let Ok(ip) = ip else {
+                break;
+            };
+
+            client_ip = ip;
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+
+        client_ip
+    }
+}
+
+fn parse_network(value: &str) -> Result<(IpAddr, u8), anyhow::Error> {
+    let (ip, prefix_len) = match value.split_once('/') {
+        Some((ip, prefix_len)) => (ip, Some(prefix_len)),
+        None => (value, None),
+    };
+
+    let ip = ip
+        .parse::<IpAddr>()
+        .with_context(|| format!("Invalid trusted proxy: {value}"))?;
+
+    let max_prefix_len = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match prefix_len {
+        Some(x) => x
+            .parse::<u8>()
+            .ok()
+            .filter(|x| *x <= max_prefix_len)
+            .with_context(|| format!("Invalid trusted proxy network: {value}"))?,
+        None => max_prefix_len,
+    };
+
+    Ok((ip, prefix_len))
+}
+
+fn network_contains(network: IpAddr, prefix_len: u8, ip: IpAddr) -> bool {
+    match (network, ip.to_canonical()) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted_proxies(networks: &[&str]) -> TrustedProxies {
+        let networks = networks.iter().map(|x| parse_network(x).unwrap()).collect();
+        TrustedProxies { networks }
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn ignores_the_header_of_untrusted_peers() {
+        let trusted_proxies = trusted_proxies(&["10.0.0.0/8"]);
+        let headers = forwarded_for("1.2.3.4");
+
+        assert_eq!(
+            trusted_proxies.client_ip(ip("5.6.7.8"), &headers),
+            ip("5.6.7.8")
+        );
+        assert_eq!(
+            TrustedProxies::default().client_ip(ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn skips_chained_trusted_proxies() {
+        let trusted_proxies = trusted_proxies(&["10.0.0.0/8", "192.168.1.1"]);
+
+        // The client can prepend anything, only the addresses added by the proxies count
+        let headers = forwarded_for("9.9.9.9, 1.2.3.4, 192.168.1.1, 10.0.0.2");
+        assert_eq!(
+            trusted_proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+
+        let mut headers = forwarded_for("1.2.3.4");
+        headers.append("x-forwarded-for", "10.0.0.2".parse().unwrap());
+        assert_eq!(
+            trusted_proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+
+        // Only trusted proxies, the leftmost one is the closest to the client
+        let headers = forwarded_for("10.0.0.3, 10.0.0.2");
+        assert_eq!(
+            trusted_proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("10.0.0.3")
+        );
+        assert_eq!(
+            trusted_proxies.client_ip(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn stops_at_malformed_entries() {
+        let trusted_proxies = trusted_proxies(&["10.0.0.0/8"]);
+
+        let headers = forwarded_for("1.2.3.4, not-an-ip, 10.0.0.2");
+        assert_eq!(
+            trusted_proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("10.0.0.2")
+        );
+
+        let headers = forwarded_for("1.2.3.4,");
+        assert_eq!(
+            trusted_proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn matches_ipv4_mapped_addresses() {
+        let trusted_proxies = trusted_proxies(&["10.0.0.0/8"]);
+        assert!(trusted_proxies.is_trusted(ip("::ffff:10.0.0.1")));
+        assert!(!trusted_proxies.is_trusted(ip("::ffff:11.0.0.1")));
+
+        let headers = forwarded_for("1.2.3.4");
+        assert_eq!(
+            trusted_proxies.client_ip(ip("::ffff:10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+    }
+
+    #[test]
+    fn matches_prefix_length_edges() {
+        assert!(network_contains(ip("0.0.0.0"), 0, ip("255.255.255.255")));
+        assert!(network_contains(ip("::"), 0, ip("ffff::1")));
+        assert!(!network_contains(ip("0.0.0.0"), 0, ip("::1")));
+
+        assert!(network_contains(ip("10.0.0.1"), 32, ip("10.0.0.1")));
+        assert!(!network_contains(ip("10.0.0.1"), 32, ip("10.0.0.2")));
+        assert!(network_contains(ip("fd00::1"), 128, ip("fd00::1")));
+        assert!(!network_contains(ip("fd00::1"), 128, ip("fd00::2")));
+
+        assert!(network_contains(ip("10.1.0.0"), 16, ip("10.1.255.255")));
+        assert!(!network_contains(ip("10.1.0.0"), 16, ip("10.2.0.0")));
+    }
+
+    #[test]
+    fn parses_networks() {
+        assert_eq!(parse_network("10.0.0.1").unwrap(), (ip("10.0.0.1"), 32));
+        assert_eq!(parse_network("fd00::/8").unwrap(), (ip("fd00::"), 8));
+        assert!(parse_network("10.0.0.0/33").is_err());
+        assert!(parse_network("fd00::/129").is_err());
+        assert!(parse_network("10.0.0.0/").is_err());
+        assert!(parse_network("localhost").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashSet, time::Duration};
+
+use crate::db::DbPool;
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use super::{NewUserSession, SessionStore};
+use crate::models::{UserSession, UserSessionInfo};
+
+/// Keeps the sessions in the `user_session` table of the database, sqlite or postgres.
+#[derive(Debug, Clone)]
+pub struct DatabaseSessionStore {
+    pool: DbPool,
+}
+
+impl DatabaseSessionStore {
+    pub fn new(pool: DbPool) -> Self {
+        DatabaseSessionStore { pool }
+    }
+}
+
+#[async_trait]
+impl SessionStore for DatabaseSessionStore {
+    async fn create(&self, session: NewUserSession<'_>) -> Result<UserSession, anyhow::Error> {
+        crate::db::create_user_session(
+            &self.pool,
+            session.token_hash,
+            session.user_id,
+            session.duration,
+            session.second_factor_pending,
+            session.persistent,
+            session.ip_address,
+            session.user_agent,
+        )
+        .await
+    }
+
+    async fn get(&self, token_hash: &str) -> Result<Option<UserSession>, anyhow::Error> {
+        crate::db::get_user_session(&self.pool, token_hash).await
+    }
+
+    async fn touch(
+        &self,
+        token_hash: &str,
+        last_seen_at: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), anyhow::Error> {
+        crate::db::touch_user_session(&self.pool, token_hash, last_seen_at, expires_at).await
+    }
+
+    async fn delete(&self, token_hash: &str) -> Result<bool, anyhow::Error> {
+        crate::db::delete_user_session(&self.pool, token_hash).await
+    }
+
+    async fn delete_for_user(
+        &self,
+        user_id: Uuid,
+        except: Option<&str>,
+    ) -> Result<u64, anyhow::Error> {
+        match except {
+            Some(token_hash) => {
+                crate::db::delete_other_user_sessions(&self.pool, user_id, token_hash).await
+            }
+            None => crate::db::delete_user_sessions(&self.pool, user_id).await,
+        }
+    }
+
+    async fn delete_by_handle(&self, user_id: Uuid, handle: i64) -> Result<bool, anyhow::Error> {
+        crate::db::delete_user_session_by_handle(&self.pool, user_id, handle).await
+    }
+
+    async fn list_for_user(
+        &self,
+        user_id: Uuid,
+        current_token_hash: Option<&str>,
+    ) -> Result<Vec<UserSessionInfo>, anyhow::Error> {
+        crate::db::get_user_sessions_info(&self.pool, user_id, current_token_hash).await
+    }
+
+    async fn complete_second_factor(
+        &self,
+        token_hash: &str,
+        duration: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        crate::db::complete_session_second_factor(&self.pool, token_hash, duration).await
+    }
+
+    async fn record_failed_second_factor(&self, token_hash: &str) -> Result<i64, anyhow::Error> {
+        crate::db::record_failed_second_factor(&self.pool, token_hash).await
+    }
+
+    async fn sweep(&self, limit: i64) -> Result<u64, anyhow::Error> {
+        crate::db::delete_expired_user_sessions(&self.pool, limit).await
+    }
+
+    async fn users_with_sessions(&self, user_ids: &[Uuid]) -> Result<HashSet<Uuid>, anyhow::Error> {
+        crate::db::get_users_with_sessions(&self.pool, user_ids).await
+    }
+}
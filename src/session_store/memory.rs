@@ -0,0 +1,213 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use super::{NewUserSession, SessionStore};
+use crate::models::{UserSession, UserSessionInfo};
+
+/// Keeps the sessions in memory, they are lost when the server stops.
+/// Meant for tests and development, or a single server that doesn't mind signing out everyone on restarts.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<MemorySessions>,
+}
+
+#[derive(Debug, Default)]
+struct MemorySessions {
+    by_token_hash: HashMap<String, StoredSession>,
+    last_handle: i64,
+}
+
+#[derive(Debug, Clone)]
+struct StoredSession {
+    session: UserSession,
+    handle: i64,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    second_factor_attempts: i64,
+}
+
+impl MemorySessionStore {
+    fn sessions(&self) -> MutexGuard<'_, MemorySessions> {
+        // A panic can't leave the sessions half updated
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn create(&self, session: NewUserSession<'_>) -> Result<UserSession, anyhow::Error> {
+        let created_at = chrono::offset::Utc::now().naive_utc();
+        let user_session = UserSession {
+            token_hash: session.token_hash.to_owned(),
+            user_id: session.user_id,
+            created_at,
+            expires_at: created_at + session.duration,
+            last_seen_at: Some(created_at),
+            second_factor_pending: session.second_factor_pending,
+            persistent: session.persistent,
+        };
+
+        let mut sessions = self.sessions();
+        sessions.last_handle += 1;
+
+        let stored_session = StoredSession {
+            session: user_session.clone(),
+            handle: sessions.last_handle,
+            ip_address: session.ip_address.map(|x| x.to_owned()),
+            user_agent: session.user_agent.map(|x| x.to_owned()),
+            second_factor_attempts: 0,
+        };
+
+        sessions
+            .by_token_hash
+            .insert(session.token_hash.to_owned(), stored_session);
+
+        Ok(user_session)
+    }
+
+    async fn get(&self, token_hash: &str) -> Result<Option<UserSession>, anyhow::Error> {
+        let sessions = self.sessions();
+        Ok(sessions
+            .by_token_hash
+            .get(token_hash)
+            .map(|x| x.session.clone()))
+    }
+
+    async fn touch(
+        &self,
+        token_hash: &str,
+        last_seen_at: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(stored_session) = self.sessions().by_token_hash.get_mut(token_hash) {
+            stored_session.session.last_seen_at = Some(last_seen_at);
+            stored_session.session.expires_at = expires_at;
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, token_hash: &str) -> Result<bool, anyhow::Error> {
+        Ok(self.sessions().by_token_hash.remove(token_hash).is_some())
+    }
+
+    async fn delete_for_user(
+        &self,
+        user_id: Uuid,
+        except: Option<&str>,
+    ) -> Result<u64, anyhow::Error> {
+        let mut sessions = self.sessions();
+        let count = sessions.by_token_hash.len();
+
+        sessions.by_token_hash.retain(|token_hash, stored_session| {
+            stored_session.session.user_id != user_id || except == Some(token_hash.as_str())
+        });
+
+        Ok((count - sessions.by_token_hash.len()) as u64)
+    }
+
+    async fn delete_by_handle(&self, user_id: Uuid, handle: i64) -> Result<bool, anyhow::Error> {
+        let mut sessions = self.sessions();
+        let token_hash = sessions
+            .by_token_hash
+            .iter()
+            .find(|(_, x)| x.handle == handle && x.session.user_id == user_id)
+            .map(|(token_hash, _)| token_hash.clone());
+
+        Ok(token_hash.is_some_and(|x| sessions.by_token_hash.remove(&x).is_some()))
+    }
+
+    async fn list_for_user(
+        &self,
+        user_id: Uuid,
+        current_token_hash: Option<&str>,
+    ) -> Result<Vec<UserSessionInfo>, anyhow::Error> {
+        let now = chrono::offset::Utc::now().naive_utc();
+        let mut sessions = self
+            .sessions()
+            .by_token_hash
+            .values()
+            .filter(|x| x.session.user_id == user_id && x.session.expires_at > now)
+            .map(|x| UserSessionInfo {
+                handle: x.handle,
+                created_at: x.session.created_at,
+                expires_at: x.session.expires_at,
+                last_seen_at: x.session.last_seen_at,
+                ip_address: x.ip_address.clone(),
+                user_agent: x.user_agent.clone(),
+                second_factor_pending: x.session.second_factor_pending,
+                current: current_token_hash == Some(x.session.token_hash.as_str()),
+            })
+            .collect::<Vec<_>>();
+
+        sessions.sort_by_key(|x| std::cmp::Reverse(x.last_seen_at.unwrap_or(x.created_at)));
+        Ok(sessions)
+    }
+
+    async fn complete_second_factor(
+        &self,
+        token_hash: &str,
+        duration: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let mut sessions = self.sessions();
+        let Some(stored_session) = sessions
+            .by_token_hash
+            .get_mut(token_hash)
+            .filter(|x| x.session.second_factor_pending)
+        else {
+            return Ok(false);
+        };
+
+        stored_session.session.second_factor_pending = false;
+        stored_session.session.expires_at = chrono::offset::Utc::now().naive_utc() + duration;
+        Ok(true)
+    }
+
+    async fn record_failed_second_factor(&self, token_hash: &str) -> Result<i64, anyhow::Error> {
+        let mut sessions = self.sessions();
+        let Some(stored_session) = sessions.by_token_hash.get_mut(token_hash) else {
+            return Ok(0);
+        };
+
+        stored_session.second_factor_attempts += 1;
+        Ok(stored_session.second_factor_attempts)
+    }
+
+    async fn sweep(&self, limit: i64) -> Result<u64, anyhow::Error> {
+        let now = chrono::offset::Utc::now().naive_utc();
+        let mut sessions = self.sessions();
+        let expired = sessions
+            .by_token_hash
+            .iter()
+            .filter(|(_, x)| now > x.session.expires_at)
+            .map(|(token_hash, _)| token_hash.clone())
+            .take(limit.max(0) as usize)
+            .collect::<Vec<_>>();
+
+        for token_hash in &expired {
+            sessions.by_token_hash.remove(token_hash);
+        }
+
+        Ok(expired.len() as u64)
+    }
+
+    async fn users_with_sessions(&self, user_ids: &[Uuid]) -> Result<HashSet<Uuid>, anyhow::Error> {
+        let now = chrono::offset::Utc::now().naive_utc();
+        let user_ids = user_ids.iter().collect::<HashSet<_>>();
+        let sessions = self.sessions();
+
+        Ok(sessions
+            .by_token_hash
+            .values()
+            .filter(|x| x.session.expires_at > now && user_ids.contains(&x.session.user_id))
+            .map(|x| x.session.user_id)
+            .collect())
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use crate::config::{ConfigReader, Secret};
+use crate::db::DbPool;
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::models::{User, UserSession, UserSessionInfo};
+
+mod database;
+mod memory;
+mod redis;
+#[cfg(test)]
+mod tests;
+
+pub use database::DatabaseSessionStore;
+pub use memory::MemorySessionStore;
+pub use redis::RedisSessionStore;
+
+/// The session store shared by the routes, it's added as an extension.
+pub type SharedSessionStore = Arc<dyn SessionStore>;
+
+/// A session that is being created, see [`SessionStore::create`].
+#[derive(Debug, Clone)]
+pub struct NewUserSession<'a> {
+    /// Only the hash of the token in the session cookie is stored.
+    pub token_hash: &'a str,
+    pub user_id: Uuid,
+    pub duration: Duration,
+    pub second_factor_pending: bool,
+    pub persistent: bool,
+    pub ip_address: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+}
+
+/// Where the sessions of the users are kept, the users themselves are always in the database.
+#[async_trait]
+pub trait SessionStore: std::fmt::Debug + Send + Sync {
+    async fn create(&self, session: NewUserSession<'_>) -> Result<UserSession, anyhow::Error>;
+
+    /// Returns a session even if it already expired, but the store may also have forgotten it.
+    async fn get(&self, token_hash: &str) -> Result<Option<UserSession>, anyhow::Error>;
+
+    /// Updates when the session was last used and when it expires.
+    async fn touch(
+        &self,
+        token_hash: &str,
+        last_seen_at: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), anyhow::Error>;
+
+    async fn delete(&self, token_hash: &str) -> Result<bool, anyhow::Error>;
+
+    /// Deletes the sessions of an user, except the one in `except`, and returns how many were deleted.
+    async fn delete_for_user(
+        &self,
+        user_id: Uuid,
+        except: Option<&str>,
+    ) -> Result<u64, anyhow::Error>;
+
+    /// Deletes a session of an user by the handle displayed in the session list.
+    async fn delete_by_handle(&self, user_id: Uuid, handle: i64) -> Result<bool, anyhow::Error>;
+
+    /// Returns the sessions of an user that are not expired, `current_token_hash` is the session making the request.
+    async fn list_for_user(
+        &self,
+        user_id: Uuid,
+        current_token_hash: Option<&str>,
+    ) -> Result<Vec<UserSessionInfo>, anyhow::Error>;
+
+    /// Marks the second factor of a pending session as verified, the session becomes a regular session.
+    async fn complete_second_factor(
+        &self,
+        token_hash: &str,
+        duration: Duration,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Increments the failed second factor attempts of a pending session and returns the current count.
+    async fn record_failed_second_factor(&self, token_hash: &str) -> Result<i64, anyhow::Error>;
+
+    /// Deletes up to `limit` expired sessions and returns how many were deleted.
+    async fn sweep(&self, limit: i64) -> Result<u64, anyhow::Error>;
+
+    /// Returns which of the given users have sessions that are not expired.
+    ///
+    /// The default lists the sessions of each user, stores should look them up at once.
+    async fn users_with_sessions(&self, user_ids: &[Uuid]) -> Result<HashSet<Uuid>, anyhow::Error> {
+        let mut users = HashSet::new();
+
+        for user_id in user_ids {
+            if !self.list_for_user(*user_id, None).await?.is_empty() {
+                users.insert(*user_id);
+            }
+        }
+
+        Ok(users)
+    }
+}
+
+/// Where the sessions are kept, set with `SESSION_STORE`, which is `database` (the default),
+/// `memory` or `redis`. The redis store connects to `REDIS_URL`, any server that speaks the redis protocol works.
+#[derive(Debug, Clone)]
+pub enum SessionStoreConfig {
+    Database,
+    Memory,
+    Redis { url: Secret },
+}
+
+impl SessionStoreConfig {
+    pub fn from_config(reader: &mut ConfigReader) -> Self {
+        let store_name = reader
+            .get("SESSION_STORE")
+            .unwrap_or_else(|| "database".to_owned());
+
+        match store_name.as_str() {
+            // `sqlite` was the name of the database store before postgres was supported
+            "database" | "sqlite" => SessionStoreConfig::Database,
+            "memory" => SessionStoreConfig::Memory,
+            "redis" => match reader.required_secret("REDIS_URL") {
+                Some(url) => SessionStoreConfig::Redis { url },
+                None => SessionStoreConfig::Database,
+            },
+            _ => {
+                reader.error(format!(
+                    "Invalid 'SESSION_STORE', expected 'database', 'memory' or 'redis': {store_name}"
+                ));
+                SessionStoreConfig::Database
+            }
+        }
+    }
+}
+
+/// Creates the session store selected in the config.
+pub async fn create_session_store(
+    config: &SessionStoreConfig,
+    pool: DbPool,
+) -> Result<SharedSessionStore, anyhow::Error> {
+    let store: SharedSessionStore = match config {
+        SessionStoreConfig::Database => Arc::new(DatabaseSessionStore::new(pool)),
+        SessionStoreConfig::Memory => Arc::new(MemorySessionStore::default()),
+        SessionStoreConfig::Redis { url } => {
+            Arc::new(RedisSessionStore::connect(url.expose()).await?)
+        }
+    };
+
+    Ok(store)
+}
+
+/// Returns the user of a session that has not expired, either a regular session or one that is still
+/// pending the second factor. Disabled users don't have valid sessions.
+///
+/// Sessions are looked up by the SHA-256 hash of the token in the cookie, the time the lookup takes
+/// can only tell about the hash, which doesn't help to guess a valid token.
+pub async fn get_session_user(
+    session_store: &dyn SessionStore,
+    pool: &DbPool,
+    token_hash: &str,
+    second_factor_pending: bool,
+) -> Result<Option<(User, UserSession)>, anyhow::Error> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let session = session_store
+        .get(token_hash)
+        .await?
+        .filter(|x| x.expires_at > now && x.second_factor_pending == second_factor_pending);
+
+    let Some(session) = session else {
+        return Ok(None);
+    };
+
+    let user = crate::db::get_enabled_user(pool, session.user_id).await?;
+    Ok(user.map(|user| (user, session)))
+}
@@ -0,0 +1,478 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+use uuid::Uuid;
+
+use super::{NewUserSession, SessionStore};
+use crate::models::{UserSession, UserSessionInfo};
+
+const SESSION_HANDLE_KEY: &str = "auth:session_handle";
+const SESSION_EXPIRY_KEY: &str = "auth:session_expiry";
+
+// The sweeper deletes the expired sessions, redis only forgets the ones left behind
+const SESSION_KEY_GRACE_PERIOD: Duration = Duration::from_millis(1000 * 60 * 60 * 24); // 1 day
+
+// The scripts check the session and update it at once, the session may be deleted at the same time.
+// KEYS are the session and the expiration index, ARGV starts with the token hash.
+
+/// Updates when the session was last used and when it expires, if it still exists.
+const TOUCH_SCRIPT: &str = r#"
+local user_id = redis.call('HGET', KEYS[1], 'user_id')
+if not user_id then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'last_seen_at', ARGV[2], 'expires_at', ARGV[3])
+redis.call('PEXPIREAT', KEYS[1], ARGV[4])
+redis.call('ZADD', KEYS[2], ARGV[3], user_id .. ':' .. ARGV[1])
+return 1
+"#;
+
+/// Turns a session pending the second factor into a regular session.
+const COMPLETE_SECOND_FACTOR_SCRIPT: &str = r#"
+local user_id = redis.call('HGET', KEYS[1], 'user_id')
+if not user_id or redis.call('HGET', KEYS[1], 'second_factor_pending') ~= '1' then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'second_factor_pending', '0', 'expires_at', ARGV[2])
+redis.call('PEXPIREAT', KEYS[1], ARGV[3])
+redis.call('ZADD', KEYS[2], ARGV[2], user_id .. ':' .. ARGV[1])
+return 1
+"#;
+
+/// Increments the failed attempts of a session, without creating the session if it was deleted.
+const RECORD_FAILED_SECOND_FACTOR_SCRIPT: &str = r#"
+if redis.call('HEXISTS', KEYS[1], 'user_id') == 0 then
+    return 0
+end
+return redis.call('HINCRBY', KEYS[1], 'second_factor_attempts', 1)
+"#;
+
+/// Keeps the sessions in a redis server, so many servers can share them.
+///
+/// Each session is a hash in `auth:session:{token_hash}`, the sessions of each user are listed in
+/// the set `auth:user_sessions:{user_id}` and all of them are ordered by expiration in `auth:session_expiry`.
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    connection: ConnectionManager,
+    touch_script: Script,
+    complete_second_factor_script: Script,
+    record_failed_second_factor_script: Script,
+}
+
+impl RedisSessionStore {
+    pub async fn connect(url: &str) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(url).context("Invalid redis url")?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to redis")?;
+
+        Ok(RedisSessionStore {
+            connection,
+            touch_script: Script::new(TOUCH_SCRIPT),
+            complete_second_factor_script: Script::new(COMPLETE_SECOND_FACTOR_SCRIPT),
+            record_failed_second_factor_script: Script::new(RECORD_FAILED_SECOND_FACTOR_SCRIPT),
+        })
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        self.connection.clone()
+    }
+
+    /// Returns the user of a session, which is needed to find its other keys.
+    async fn get_user_id(&self, token_hash: &str) -> Result<Option<Uuid>, anyhow::Error> {
+        let user_id: Option<String> = self
+            .connection()
+            .hget(session_key(token_hash), "user_id")
+            .await?;
+
+        Ok(user_id.and_then(|x| x.parse().ok()))
+    }
+
+    /// Deletes sessions of an user and returns how many existed.
+    async fn delete_sessions(
+        &self,
+        user_id: Uuid,
+        token_hashes: &[String],
+    ) -> Result<u64, anyhow::Error> {
+        if token_hashes.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for token_hash in token_hashes {
+            pipe.del(session_key(token_hash))
+                .srem(user_sessions_key(user_id), token_hash)
+                .ignore()
+                .zrem(SESSION_EXPIRY_KEY, expiry_member(user_id, token_hash))
+                .ignore();
+        }
+
+        let deleted: Vec<u64> = pipe.query_async(&mut self.connection()).await?;
+        Ok(deleted.into_iter().sum())
+    }
+
+    /// Sets when the session expires, in the session and in the expiration index.
+    fn set_expires_at(
+        pipe: &mut redis::Pipeline,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) {
+        let expires_at_ms = to_millis(expires_at);
+        let key_expires_at_ms = to_millis(expires_at + SESSION_KEY_GRACE_PERIOD);
+
+        pipe.hset(session_key(token_hash), "expires_at", expires_at_ms)
+            .ignore()
+            .cmd("PEXPIREAT")
+            .arg(session_key(token_hash))
+            .arg(key_expires_at_ms)
+            .ignore()
+            .zadd(
+                SESSION_EXPIRY_KEY,
+                expiry_member(user_id, token_hash),
+                expires_at_ms,
+            )
+            .ignore();
+    }
+}
+
+impl std::fmt::Debug for RedisSessionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisSessionStore").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn create(&self, session: NewUserSession<'_>) -> Result<UserSession, anyhow::Error> {
+        let created_at = chrono::offset::Utc::now().naive_utc();
+        let user_session = UserSession {
+            token_hash: session.token_hash.to_owned(),
+            user_id: session.user_id,
+            created_at,
+            expires_at: created_at + session.duration,
+            last_seen_at: Some(created_at),
+            second_factor_pending: session.second_factor_pending,
+            persistent: session.persistent,
+        };
+
+        let mut connection = self.connection();
+        let handle: i64 = connection.incr(SESSION_HANDLE_KEY, 1).await?;
+
+        let mut fields = vec![
+            ("user_id", session.user_id.to_string()),
+            ("handle", handle.to_string()),
+            ("created_at", to_millis(created_at).to_string()),
+            ("last_seen_at", to_millis(created_at).to_string()),
+            (
+                "second_factor_pending",
+                u8::from(session.second_factor_pending).to_string(),
+            ),
+            ("persistent", u8::from(session.persistent).to_string()),
+            ("second_factor_attempts", "0".to_owned()),
+        ];
+
+        if let Some(ip_address) = session.ip_address {
+            fields.push(("ip_address", ip_address.to_owned()));
+        }
+
+        if let Some(user_agent) = session.user_agent {
+            fields.push(("user_agent", user_agent.to_owned()));
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hset_multiple(session_key(session.token_hash), &fields)
+            .ignore()
+            .sadd(user_sessions_key(session.user_id), session.token_hash)
+            .ignore();
+
+        Self::set_expires_at(
+            &mut pipe,
+            session.user_id,
+            session.token_hash,
+            user_session.expires_at,
+        );
+
+        pipe.query_async::<_, ()>(&mut connection).await?;
+        Ok(user_session)
+    }
+
+    async fn get(&self, token_hash: &str) -> Result<Option<UserSession>, anyhow::Error> {
+        let fields: HashMap<String, String> =
+            self.connection().hgetall(session_key(token_hash)).await?;
+
+        Ok(StoredSession::from_fields(token_hash, &fields).map(|x| x.session))
+    }
+
+    async fn touch(
+        &self,
+        token_hash: &str,
+        last_seen_at: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), anyhow::Error> {
+        self.touch_script
+            .key(session_key(token_hash))
+            .key(SESSION_EXPIRY_KEY)
+            .arg(token_hash)
+            .arg(to_millis(last_seen_at))
+            .arg(to_millis(expires_at))
+            .arg(to_millis(expires_at + SESSION_KEY_GRACE_PERIOD))
+            .invoke_async::<_, ()>(&mut self.connection())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, token_hash: &str) -> Result<bool, anyhow::Error> {
+        let Some(user_id) = self.get_user_id(token_hash).await? else {
+            return Ok(false);
+        };
+
+        let deleted = self
+            .delete_sessions(user_id, &[token_hash.to_owned()])
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn delete_for_user(
+        &self,
+        user_id: Uuid,
+        except: Option<&str>,
+    ) -> Result<u64, anyhow::Error> {
+        let token_hashes: Vec<String> = self
+            .connection()
+            .smembers(user_sessions_key(user_id))
+            .await?;
+
+        let token_hashes = token_hashes
+            .into_iter()
+            .filter(|x| except != Some(x.as_str()))
+            .collect::<Vec<_>>();
+
+        self.delete_sessions(user_id, &token_hashes).await
+    }
+
+    async fn delete_by_handle(&self, user_id: Uuid, handle: i64) -> Result<bool, anyhow::Error> {
+        let mut connection = self.connection();
+        let token_hashes: Vec<String> = connection.smembers(user_sessions_key(user_id)).await?;
+
+        for token_hash in token_hashes {
+            let session_handle: Option<i64> =
+                connection.hget(session_key(&token_hash), "handle").await?;
+
+            if session_handle == Some(handle) {
+                let deleted = self.delete_sessions(user_id, &[token_hash]).await?;
+                return Ok(deleted > 0);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn list_for_user(
+        &self,
+        user_id: Uuid,
+        current_token_hash: Option<&str>,
+    ) -> Result<Vec<UserSessionInfo>, anyhow::Error> {
+        let mut connection = self.connection();
+        let token_hashes: Vec<String> = connection.smembers(user_sessions_key(user_id)).await?;
+        if token_hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipe = redis::pipe();
+        for token_hash in &token_hashes {
+            pipe.hgetall(session_key(token_hash));
+        }
+
+        let all_fields: Vec<HashMap<String, String>> = pipe.query_async(&mut connection).await?;
+        let now = chrono::offset::Utc::now().naive_utc();
+
+        let mut sessions = token_hashes
+            .iter()
+            .zip(all_fields)
+            .filter_map(|(token_hash, fields)| StoredSession::from_fields(token_hash, &fields))
+            .filter(|x| x.session.user_id == user_id && x.session.expires_at > now)
+            .map(|x| UserSessionInfo {
+                handle: x.handle,
+                created_at: x.session.created_at,
+                expires_at: x.session.expires_at,
+                last_seen_at: x.session.last_seen_at,
+                ip_address: x.ip_address,
+                user_agent: x.user_agent,
+                second_factor_pending: x.session.second_factor_pending,
+                current: current_token_hash == Some(x.session.token_hash.as_str()),
+            })
+            .collect::<Vec<_>>();
+
+        sessions.sort_by_key(|x| std::cmp::Reverse(x.last_seen_at.unwrap_or(x.created_at)));
+        Ok(sessions)
+    }
+
+    async fn complete_second_factor(
+        &self,
+        token_hash: &str,
+        duration: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let expires_at = chrono::offset::Utc::now().naive_utc() + duration;
+        let completed: bool = self
+            .complete_second_factor_script
+            .key(session_key(token_hash))
+            .key(SESSION_EXPIRY_KEY)
+            .arg(token_hash)
+            .arg(to_millis(expires_at))
+            .arg(to_millis(expires_at + SESSION_KEY_GRACE_PERIOD))
+            .invoke_async(&mut self.connection())
+            .await?;
+
+        Ok(completed)
+    }
+
+    async fn record_failed_second_factor(&self, token_hash: &str) -> Result<i64, anyhow::Error> {
+        let attempts: i64 = self
+            .record_failed_second_factor_script
+            .key(session_key(token_hash))
+            .invoke_async(&mut self.connection())
+            .await?;
+
+        Ok(attempts)
+    }
+
+    async fn sweep(&self, limit: i64) -> Result<u64, anyhow::Error> {
+        let now = to_millis(chrono::offset::Utc::now().naive_utc());
+        let mut connection = self.connection();
+        let members: Vec<String> = connection
+            .zrangebyscore_limit(SESSION_EXPIRY_KEY, "-inf", now, 0, limit as isize)
+            .await?;
+
+        if members.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for member in &members {
+            pipe.zrem(SESSION_EXPIRY_KEY, member).ignore();
+
+            let Some((user_id, token_hash)) = member.split_once(':') else {
+                continue;
+            };
+
+            let Ok(user_id) = user_id.parse::<Uuid>() else {
+                continue;
+            };
+
+            pipe.del(session_key(token_hash))
+                .ignore()
+                .srem(user_sessions_key(user_id), token_hash)
+                .ignore();
+        }
+
+        pipe.query_async::<_, ()>(&mut connection).await?;
+        Ok(members.len() as u64)
+    }
+
+    async fn users_with_sessions(&self, user_ids: &[Uuid]) -> Result<HashSet<Uuid>, anyhow::Error> {
+        if user_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let mut connection = self.connection();
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.smembers(user_sessions_key(*user_id));
+        }
+
+        // The sets may still have expired sessions, so their expiration is read too
+        let all_token_hashes: Vec<Vec<String>> = pipe.query_async(&mut connection).await?;
+        let sessions = user_ids
+            .iter()
+            .zip(all_token_hashes)
+            .flat_map(|(user_id, token_hashes)| token_hashes.into_iter().map(|x| (*user_id, x)))
+            .collect::<Vec<_>>();
+
+        if sessions.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let mut pipe = redis::pipe();
+        for (_, token_hash) in &sessions {
+            pipe.hget(session_key(token_hash), "expires_at");
+        }
+
+        let all_expires_at: Vec<Option<i64>> = pipe.query_async(&mut connection).await?;
+        let now = to_millis(chrono::offset::Utc::now().naive_utc());
+
+        Ok(sessions
+            .into_iter()
+            .zip(all_expires_at)
+            .filter(|(_, expires_at)| expires_at.is_some_and(|x| x > now))
+            .map(|((user_id, _), _)| user_id)
+            .collect())
+    }
+}
+
+/// A session with the details that are only displayed in the session list.
+struct StoredSession {
+    session: UserSession,
+    handle: i64,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl StoredSession {
+    /// Returns the session in the fields of its hash, if the hash is missing or incomplete there is no session.
+    fn from_fields(token_hash: &str, fields: &HashMap<String, String>) -> Option<Self> {
+        let session = UserSession {
+            token_hash: token_hash.to_owned(),
+            user_id: fields.get("user_id")?.parse().ok()?,
+            created_at: from_millis(fields.get("created_at")?)?,
+            expires_at: from_millis(fields.get("expires_at")?)?,
+            last_seen_at: fields.get("last_seen_at").and_then(|x| from_millis(x)),
+            second_factor_pending: fields.get("second_factor_pending")? == "1",
+            persistent: fields.get("persistent")? == "1",
+        };
+
+        Some(StoredSession {
+            session,
+            handle: fields.get("handle")?.parse().ok()?,
+            ip_address: fields.get("ip_address").cloned(),
+            user_agent: fields.get("user_agent").cloned(),
+        })
+    }
+}
+
+fn session_key(token_hash: &str) -> String {
+    format!("auth:session:{token_hash}")
+}
+
+fn user_sessions_key(user_id: Uuid) -> String {
+    format!("auth:user_sessions:{user_id}")
+}
+
+// The scripts build the same member
+fn expiry_member(user_id: Uuid, token_hash: &str) -> String {
+    format!("{user_id}:{token_hash}")
+}
+
+fn to_millis(date_time: NaiveDateTime) -> i64 {
+    date_time.and_utc().timestamp_millis()
+}
+
+fn from_millis(value: &str) -> Option<NaiveDateTime> {
+    value
+        .parse()
+        .ok()
+        .and_then(NaiveDateTime::from_timestamp_millis)
+}
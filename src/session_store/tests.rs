@@ -0,0 +1,387 @@
+//! The same tests run against each store. The redis store is only tested when `TEST_REDIS_URL`
+//! is set, and the database store on postgres when `TEST_POSTGRES_URL` is set.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use chrono::{NaiveDateTime, Timelike};
+use uuid::Uuid;
+
+use super::{MemorySessionStore, NewUserSession, SessionStore, SharedSessionStore};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// A store with two users that exist, the database stores require the users.
+struct Fixture {
+    store: SharedSessionStore,
+    user_ids: [Uuid; 2],
+}
+
+async fn memory() -> Option<Fixture> {
+    Some(Fixture {
+        store: Arc::new(MemorySessionStore::default()),
+        user_ids: [Uuid::new_v4(), Uuid::new_v4()],
+    })
+}
+
+#[cfg(feature = "sqlite")]
+async fn sqlite() -> Option<Fixture> {
+    // Each pool has its own database, shared by its connections
+    database("sqlite::memory:").await
+}
+
+#[cfg(feature = "postgres")]
+async fn postgres() -> Option<Fixture> {
+    let url = std::env::var("TEST_POSTGRES_URL").ok()?;
+    database(&url).await
+}
+
+async fn database(url: &str) -> Option<Fixture> {
+    let pool = crate::db::DbPool::connect(url, true).await.unwrap();
+    pool.run_migrations().await.unwrap();
+
+    let mut user_ids = [Uuid::nil(); 2];
+    for user_id in &mut user_ids {
+        let account_id = Uuid::new_v4().to_string();
+        let user = crate::db::create_user(
+            &pool,
+            account_id,
+            crate::models::AuthProvider::Guest,
+            "test".to_owned(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        *user_id = user.id;
+    }
+
+    Some(Fixture {
+        store: Arc::new(super::DatabaseSessionStore::new(pool)),
+        user_ids,
+    })
+}
+
+async fn redis() -> Option<Fixture> {
+    let url = std::env::var("TEST_REDIS_URL").ok()?;
+    let store = super::RedisSessionStore::connect(&url).await.unwrap();
+
+    Some(Fixture {
+        store: Arc::new(store),
+        user_ids: [Uuid::new_v4(), Uuid::new_v4()],
+    })
+}
+
+fn new_token_hash() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+fn new_session(token_hash: &str, user_id: Uuid, duration: Duration) -> NewUserSession<'_> {
+    NewUserSession {
+        token_hash,
+        user_id,
+        duration,
+        second_factor_pending: false,
+        persistent: false,
+        ip_address: Some("127.0.0.1"),
+        user_agent: Some("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0"),
+    }
+}
+
+/// The stores keep at least millisecond precision.
+fn now_in_seconds() -> NaiveDateTime {
+    let now = chrono::offset::Utc::now().naive_utc();
+    now.with_nanosecond(0).unwrap()
+}
+
+async fn create_and_get(store: &dyn SessionStore, [user_id, _]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    let created = store
+        .create(NewUserSession {
+            persistent: true,
+            ..new_session(&token_hash, user_id, HOUR)
+        })
+        .await
+        .unwrap();
+
+    let session = store.get(&token_hash).await.unwrap().unwrap();
+    assert_eq!(session.token_hash, token_hash);
+    assert_eq!(session.user_id, user_id);
+    assert!(session.persistent);
+    assert!(!session.second_factor_pending);
+    assert_eq!(
+        session.expires_at.and_utc().timestamp(),
+        created.expires_at.and_utc().timestamp()
+    );
+
+    assert!(store.get(&new_token_hash()).await.unwrap().is_none());
+}
+
+async fn touch(store: &dyn SessionStore, [user_id, _]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(new_session(&token_hash, user_id, HOUR))
+        .await
+        .unwrap();
+
+    let last_seen_at = now_in_seconds();
+    let expires_at = last_seen_at + HOUR * 2;
+    store
+        .touch(&token_hash, last_seen_at, expires_at)
+        .await
+        .unwrap();
+
+    let session = store.get(&token_hash).await.unwrap().unwrap();
+    assert_eq!(session.last_seen_at, Some(last_seen_at));
+    assert_eq!(session.expires_at, expires_at);
+
+    // A deleted session is not created again
+    let missing_token_hash = new_token_hash();
+    store
+        .touch(&missing_token_hash, last_seen_at, expires_at)
+        .await
+        .unwrap();
+    assert!(store.get(&missing_token_hash).await.unwrap().is_none());
+}
+
+async fn delete(store: &dyn SessionStore, [user_id, _]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(new_session(&token_hash, user_id, HOUR))
+        .await
+        .unwrap();
+
+    assert!(store.delete(&token_hash).await.unwrap());
+    assert!(!store.delete(&token_hash).await.unwrap());
+    assert!(store.get(&token_hash).await.unwrap().is_none());
+}
+
+async fn delete_for_user(store: &dyn SessionStore, [user_id, other_user_id]: [Uuid; 2]) {
+    let token_hashes = [new_token_hash(), new_token_hash(), new_token_hash()];
+    for token_hash in &token_hashes {
+        store
+            .create(new_session(token_hash, user_id, HOUR))
+            .await
+            .unwrap();
+    }
+
+    let other_token_hash = new_token_hash();
+    store
+        .create(new_session(&other_token_hash, other_user_id, HOUR))
+        .await
+        .unwrap();
+
+    let deleted = store
+        .delete_for_user(user_id, Some(&token_hashes[0]))
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2);
+
+    let sessions = store
+        .list_for_user(user_id, Some(&token_hashes[0]))
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+
+    assert_eq!(store.delete_for_user(user_id, None).await.unwrap(), 1);
+    assert!(store.get(&other_token_hash).await.unwrap().is_some());
+}
+
+async fn delete_by_handle(store: &dyn SessionStore, [user_id, other_user_id]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(new_session(&token_hash, user_id, HOUR))
+        .await
+        .unwrap();
+
+    let sessions = store.list_for_user(user_id, None).await.unwrap();
+    let handle = sessions[0].handle;
+
+    // The handle is only valid for the sessions of the same user
+    assert!(!store.delete_by_handle(other_user_id, handle).await.unwrap());
+    assert!(store.delete_by_handle(user_id, handle).await.unwrap());
+    assert!(store.get(&token_hash).await.unwrap().is_none());
+}
+
+async fn list_for_user(store: &dyn SessionStore, [user_id, other_user_id]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(new_session(&token_hash, user_id, HOUR))
+        .await
+        .unwrap();
+
+    let expired_token_hash = new_token_hash();
+    store
+        .create(new_session(&expired_token_hash, user_id, Duration::ZERO))
+        .await
+        .unwrap();
+
+    store
+        .create(new_session(&new_token_hash(), other_user_id, HOUR))
+        .await
+        .unwrap();
+
+    let sessions = store.list_for_user(user_id, None).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(!sessions[0].current);
+    assert_eq!(sessions[0].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(sessions[0].device(), "Firefox on Linux");
+}
+
+async fn complete_second_factor(store: &dyn SessionStore, [user_id, _]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(NewUserSession {
+            second_factor_pending: true,
+            ..new_session(&token_hash, user_id, Duration::from_secs(60))
+        })
+        .await
+        .unwrap();
+
+    assert!(store
+        .complete_second_factor(&token_hash, HOUR)
+        .await
+        .unwrap());
+
+    let session = store.get(&token_hash).await.unwrap().unwrap();
+    assert!(!session.second_factor_pending);
+    assert!(session.expires_at > chrono::offset::Utc::now().naive_utc() + HOUR / 2);
+
+    // Only once, and only for pending sessions that exist
+    assert!(!store
+        .complete_second_factor(&token_hash, HOUR)
+        .await
+        .unwrap());
+
+    let missing_token_hash = new_token_hash();
+    assert!(!store
+        .complete_second_factor(&missing_token_hash, HOUR)
+        .await
+        .unwrap());
+    assert!(store.get(&missing_token_hash).await.unwrap().is_none());
+}
+
+async fn record_failed_second_factor(store: &dyn SessionStore, [user_id, _]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(NewUserSession {
+            second_factor_pending: true,
+            ..new_session(&token_hash, user_id, Duration::from_secs(60))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store
+            .record_failed_second_factor(&token_hash)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        store
+            .record_failed_second_factor(&token_hash)
+            .await
+            .unwrap(),
+        2
+    );
+
+    let missing_token_hash = new_token_hash();
+    assert_eq!(
+        store
+            .record_failed_second_factor(&missing_token_hash)
+            .await
+            .unwrap(),
+        0
+    );
+    assert!(store.get(&missing_token_hash).await.unwrap().is_none());
+}
+
+async fn sweep(store: &dyn SessionStore, [user_id, _]: [Uuid; 2]) {
+    let token_hash = new_token_hash();
+    store
+        .create(new_session(&token_hash, user_id, HOUR))
+        .await
+        .unwrap();
+
+    let expired_token_hash = new_token_hash();
+    store
+        .create(new_session(&expired_token_hash, user_id, Duration::ZERO))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // Other tests may leave expired sessions in a shared store
+    while store.sweep(100).await.unwrap() == 100 {}
+
+    assert!(store.get(&expired_token_hash).await.unwrap().is_none());
+    assert!(store.get(&token_hash).await.unwrap().is_some());
+}
+
+async fn users_with_sessions(store: &dyn SessionStore, [user_id, other_user_id]: [Uuid; 2]) {
+    store
+        .create(new_session(&new_token_hash(), user_id, HOUR))
+        .await
+        .unwrap();
+    store
+        .create(new_session(
+            &new_token_hash(),
+            other_user_id,
+            Duration::ZERO,
+        ))
+        .await
+        .unwrap();
+
+    let users = store
+        .users_with_sessions(&[user_id, other_user_id, Uuid::new_v4()])
+        .await
+        .unwrap();
+    assert_eq!(users, HashSet::from([user_id]));
+
+    assert!(store.users_with_sessions(&[]).await.unwrap().is_empty());
+}
+
+macro_rules! session_store_tests {
+    ($store:ident: $($test:ident),* $(,)?) => {
+        mod $store {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    let Some(fixture) = super::$store().await else {
+                        return;
+                    };
+
+                    super::$test(&*fixture.store, fixture.user_ids).await;
+                }
+            )*
+        }
+    };
+}
+
+macro_rules! all_session_store_tests {
+    ($($store:ident),*) => {
+        $(
+            session_store_tests!(
+                $store: create_and_get,
+                touch,
+                delete,
+                delete_for_user,
+                delete_by_handle,
+                list_for_user,
+                complete_second_factor,
+                record_failed_second_factor,
+                sweep,
+                users_with_sessions,
+            );
+        )*
+    };
+}
+
+all_session_store_tests!(memory, redis);
+
+#[cfg(feature = "sqlite")]
+all_session_store_tests!(sqlite);
+
+#[cfg(feature = "postgres")]
+all_session_store_tests!(postgres);
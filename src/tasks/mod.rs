@@ -0,0 +1,212 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::db::DbPool;
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::config::ConfigReader;
+use crate::constants::{GUEST_PRUNE_GRACE_PERIOD, SWEEP_BATCH_SIZE, SWEEP_INTERVAL};
+use crate::session_store::{SessionStore, SharedSessionStore};
+
+/// How often the expired data is deleted and how many rows at once, set with
+/// `SWEEP_INTERVAL_SECONDS` and `SWEEP_BATCH_SIZE`.
+#[derive(Debug, Clone, Copy)]
+pub struct SweeperConfig {
+    pub interval: Duration,
+    pub batch_size: i64,
+}
+
+impl SweeperConfig {
+    pub fn from_config(reader: &mut ConfigReader) -> Self {
+        let interval = reader.parse_with("SWEEP_INTERVAL_SECONDS", "a number of seconds", |x| {
+            x.parse::<u64>()
+                .ok()
+                .filter(|x| *x > 0)
+                .map(Duration::from_secs)
+        });
+
+        let batch_size = reader.parse_with("SWEEP_BATCH_SIZE", "a number of rows", |x| {
+            x.parse::<i64>().ok().filter(|x| *x > 0)
+        });
+
+        SweeperConfig {
+            interval: interval.unwrap_or(SWEEP_INTERVAL),
+            batch_size: batch_size.unwrap_or(SWEEP_BATCH_SIZE),
+        }
+    }
+}
+
+/// Starts the background tasks of the server, they finish once `shutdown` is set to `true`.
+pub fn spawn_background_tasks(
+    pool: DbPool,
+    session_store: SharedSessionStore,
+    config: SweeperConfig,
+    shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(sweeper(pool, session_store, config, shutdown))
+}
+
+/// How many rows a sweep deleted.
+#[derive(Debug, Default)]
+struct SweepReport {
+    user_sessions: u64,
+    email_login_tokens: u64,
+    passkey_challenges: u64,
+    guest_users: usize,
+}
+
+/// Deletes the expired sessions and the state of the sign in flows that were abandoned,
+/// like email links that were never opened, and the guests without sessions left.
+///
+/// Each sweep records the `sweeper_deleted_total` counter, by `kind` of row, the `sweeper_duration_seconds`
+/// histogram and the `sweeper_errors_total` counter, they are exported by the recorder the application installs.
+async fn sweeper(
+    pool: DbPool,
+    session_store: SharedSessionStore,
+    config: SweeperConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        let started_at = Instant::now();
+        let report = match sweep(&pool, &*session_store, config.batch_size, &shutdown).await {
+            Ok(report) => report,
+            Err(err) => {
+                metrics::counter!("sweeper_errors_total").increment(1);
+                tracing::error!("failed to sweep expired data: {err}");
+                continue;
+            }
+        };
+
+        let elapsed = started_at.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let SweepReport {
+            user_sessions,
+            email_login_tokens,
+            passkey_challenges,
+            guest_users,
+        } = report;
+
+        metrics::histogram!("sweeper_duration_seconds").record(elapsed.as_secs_f64());
+        for (kind, deleted) in [
+            ("user_sessions", user_sessions),
+            ("email_login_tokens", email_login_tokens),
+            ("passkey_challenges", passkey_challenges),
+            ("guest_users", guest_users as u64),
+        ] {
+            metrics::counter!("sweeper_deleted_total", "kind" => kind).increment(deleted);
+        }
+
+        if user_sessions + email_login_tokens + passkey_challenges + guest_users as u64 > 0 {
+            tracing::info!(
+                user_sessions,
+                email_login_tokens,
+                passkey_challenges,
+                guest_users,
+                elapsed_ms,
+                "expired data was deleted"
+            );
+        } else {
+            tracing::debug!(elapsed_ms, "no expired data to delete");
+        }
+    }
+
+    tracing::info!("sweeper stopped");
+}
+
+async fn sweep(
+    pool: &DbPool,
+    session_store: &dyn SessionStore,
+    batch_size: i64,
+    shutdown: &watch::Receiver<bool>,
+) -> Result<SweepReport, anyhow::Error> {
+    let mut report = SweepReport {
+        user_sessions: delete_in_batches(batch_size, shutdown, || session_store.sweep(batch_size))
+            .await?,
+        email_login_tokens: delete_in_batches(batch_size, shutdown, || {
+            crate::db::delete_expired_email_login_tokens(pool, batch_size)
+        })
+        .await?,
+        passkey_challenges: delete_in_batches(batch_size, shutdown, || {
+            crate::db::delete_expired_passkey_challenges(pool, batch_size)
+        })
+        .await?,
+        ..Default::default()
+    };
+
+    // Guests without sessions can't sign in again
+    if !*shutdown.borrow() {
+        report.guest_users =
+            prune_stale_guest_users(pool, session_store, batch_size, shutdown).await?;
+    }
+
+    Ok(report)
+}
+
+/// Deletes the guests that don't have any active session, these can't be accessed anymore.
+async fn prune_stale_guest_users(
+    pool: &DbPool,
+    session_store: &dyn SessionStore,
+    batch_size: i64,
+    shutdown: &watch::Receiver<bool>,
+) -> Result<usize, anyhow::Error> {
+    // A guest that was just created doesn't have a session yet
+    let created_before = chrono::offset::Utc::now().naive_utc() - GUEST_PRUNE_GRACE_PERIOD;
+    let mut after_id = None;
+    let mut deleted = 0;
+
+    // The guests are read a page at a time, and the sessions of a page are looked up at once
+    loop {
+        let guest_ids =
+            crate::db::get_guest_user_ids(pool, created_before, after_id, batch_size).await?;
+        let active_guest_ids = session_store.users_with_sessions(&guest_ids).await?;
+
+        for guest_id in guest_ids.iter().filter(|x| !active_guest_ids.contains(x)) {
+            // The expired sessions may not be swept yet
+            session_store.delete_for_user(*guest_id, None).await?;
+            if crate::db::delete_user(pool, *guest_id).await? {
+                deleted += 1;
+            }
+        }
+
+        if guest_ids.len() < batch_size as usize || *shutdown.borrow() {
+            return Ok(deleted);
+        }
+
+        after_id = guest_ids.last().copied();
+        tokio::task::yield_now().await;
+    }
+}
+
+/// Deletes batches until one is not full, other queries can use the database between batches.
+async fn delete_in_batches<F, Fut>(
+    batch_size: i64,
+    shutdown: &watch::Receiver<bool>,
+    mut delete_batch: F,
+) -> Result<u64, anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<u64, anyhow::Error>>,
+{
+    let mut deleted = 0;
+
+    loop {
+        let batch = delete_batch().await?;
+        deleted += batch;
+
+        if batch < batch_size as u64 || *shutdown.borrow() {
+            return Ok(deleted);
+        }
+
+        tokio::task::yield_now().await;
+    }
+}
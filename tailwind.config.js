@@ -1,6 +1,6 @@
 /** @type {import('tailwindcss').Config} */
 module.exports = {
-  content: ["./src/**/*.{html,js}", "./templates/**/*.{html,js}"],
+  content: ["./src/**/*.{html,js,rs}", "./templates/**/*.{html,js}", "./public/js/**/*.js"],
   darkMode: "class",
   theme: {
     extend: {},